@@ -0,0 +1,107 @@
+//! Per command work of MiSession::request and MiSession::read_into without bluetooth: encode and encrypt command,
+//! assemble response notifications and decrypt them. Allocations per command are printed next to timings
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hex_literal::hex;
+use ninebot_ble::mi_crypto::{decrypt_uart, decrypt_uart_into, encrypt_uart, encrypt_uart_into, EncryptionKey};
+use ninebot_ble::protocol::FrameAssembler;
+use ninebot_ble::protocol_core::{Attribute, Direction, ReadWrite, ScooterCommand};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Keys and frames from tests/uart_test.rs, response is split into 20 byte notifications like scooter sends it
+const APP_KEY: EncryptionKey = EncryptionKey {
+    key: hex!("5066d82368375a1f6a0a3eba1317b525"),
+    iv: hex!("28cee53e"),
+};
+const DEV_KEY: EncryptionKey = EncryptionKey {
+    key: hex!("462f3fcc74200ca5f77ee2a581c42af0"),
+    iv: hex!("f8901a05"),
+};
+const RESPONSE: [u8; 32] = hex!("55ab1001009a70888f3a27d8378bb07f7d8ce4cce88ab54a50595ad6c019c7f2");
+const RAND: [u8; 4] = hex!("897045e7");
+
+fn command() -> ScooterCommand {
+    ScooterCommand {
+        direction: Direction::MasterToMotor,
+        read_write: ReadWrite::Read,
+        attribute: Attribute::GeneralInfo,
+        payload: vec![0x0e],
+    }
+}
+
+/// Same steps as request: every command and response gets fresh buffers
+fn request(cmd: &ScooterCommand) -> (Vec<u8>, Vec<u8>) {
+    let frame = encrypt_uart(&APP_KEY, &cmd.as_bytes(), 0, Some(RAND));
+
+    let mut assembler = FrameAssembler::new();
+    let response = RESPONSE.chunks(20).find_map(|chunk| assembler.push(chunk)).unwrap();
+    (frame, decrypt_uart(&DEV_KEY, &response).unwrap())
+}
+
+#[derive(Default)]
+struct Buffers {
+    command: Vec<u8>,
+    frame: Vec<u8>,
+    response: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+/// Same steps as read_into: buffers of session and caller are reused
+fn read_into(cmd: &ScooterCommand, buffers: &mut Buffers) {
+    cmd.write_bytes(&mut buffers.command);
+    encrypt_uart_into(&APP_KEY, &buffers.command, 0, Some(RAND), &mut buffers.frame);
+
+    let mut assembler = FrameAssembler::with_buffer(std::mem::take(&mut buffers.response));
+    buffers.response = RESPONSE.chunks(20).find_map(|chunk| assembler.push(chunk)).unwrap();
+    decrypt_uart_into(&DEV_KEY, &buffers.response, &mut buffers.buf).unwrap();
+}
+
+fn allocations(mut f: impl FnMut()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn bench_request(c: &mut Criterion) {
+    let cmd = command();
+    println!("request: {} allocations per command", allocations(|| { black_box(request(&cmd)); }));
+
+    c.bench_function("request", |b| b.iter(|| request(black_box(&cmd))));
+}
+
+fn bench_read_into(c: &mut Criterion) {
+    let cmd = command();
+    let mut buffers = Buffers::default();
+    // first command sizes buffers, later ones reuse them
+    read_into(&cmd, &mut buffers);
+    println!("read_into: {} allocations per command", allocations(|| read_into(&cmd, &mut buffers)));
+
+    c.bench_function("read_into", |b| b.iter(|| read_into(black_box(&cmd), &mut buffers)));
+}
+
+criterion_group!(benches, bench_request, bench_read_into);
+criterion_main!(benches);
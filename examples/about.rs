@@ -1,5 +1,4 @@
 use anyhow::{Result, Context};
-use btleplug::api::{BDAddr};
 use tracing_subscriber;
 use tokio::io::AsyncReadExt;
 use std::path::Path;
@@ -8,6 +7,7 @@ use tracing::Level;
 use std::env;
 use tracing_subscriber::fmt::format::FmtSpan;
 use ninebot_ble::{
+  parse_mac,
   AuthToken,
   ScooterScanner,
   LoginRequest,
@@ -41,7 +41,7 @@ async fn main() -> Result<()>{
   let token = load_token().await
     .with_context(|| "Could not load registration token")?;
 
-  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
+  let mac = parse_mac(&args[1]).expect("Invalid mac address");
   tracing::info!("Searching scooter with address: {}", mac);
 
   let mut scanner = ScooterScanner::new().await?;
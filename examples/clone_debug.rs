@@ -6,6 +6,7 @@ use btleplug::platform::Manager;
 use uuid::Uuid;
 use futures::stream::StreamExt;
 use futures::FutureExt;
+use ninebot_ble::protocol::xiaomi_checksum;
 
 // Known UUIDs from your device
 const UPNP_UUID: Uuid = Uuid::from_u128(0x00000010_0000_1000_8000_00805f9b34fb);
@@ -13,15 +14,10 @@ const AVDTP_UUID: Uuid = Uuid::from_u128(0x00000019_0000_1000_8000_00805f9b34fb)
 const NUS_TX_UUID: Uuid = Uuid::from_u128(0x6e400002_b5a3_f393_e0a9_e50e24dcca9e);
 const NUS_RX_UUID: Uuid = Uuid::from_u128(0x6e400003_b5a3_f393_e0a9_e50e24dcca9e);
 
-fn calculate_checksum(data: &[u8]) -> u16 {
-    let sum: u32 = data.iter().map(|&b| b as u32).sum();
-    ((sum ^ 0xFFFF) & 0xFFFF) as u16
-}
-
 fn build_xiaomi_packet(payload: &[u8]) -> Vec<u8> {
     let mut packet = vec![0x55, 0xAA];
     packet.extend_from_slice(payload);
-    let checksum = calculate_checksum(payload);
+    let checksum = xiaomi_checksum(payload);
     packet.push((checksum & 0xFF) as u8);
     packet.push((checksum >> 8) as u8);
     packet
@@ -4,62 +4,17 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::time;
 use tokio::sync::mpsc;
-use btleplug::api::BDAddr;
 use anyhow::Result;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
-use chrono::{Local, DateTime};
+use chrono::Local;
 
 use ninebot_ble::{
+    parse_mac,
     ScooterScanner, ConnectionHelper, LoginRequest, session::MiSession,
-    AuthToken, session::TailLight
+    AuthToken, session::{TailLight, Telemetry, SessionContinuity}
 };
 
-// Data structures for logging
-#[derive(Debug, Clone)]
-struct ScooterStatus {
-    timestamp: DateTime<Local>,
-    battery_percent: u16,
-    speed_kmh: f32,
-    avg_speed_kmh: f32,
-    trip_m: i16,
-    total_m: u32,
-    frame_temp: f32,
-    uptime_s: u64,
-    voltage: f32,
-    current: f32,
-    capacity: u16,
-    batt_temp_1: u8,
-    batt_temp_2: u8,
-    range_km: f32,
-}
-
-impl ScooterStatus {
-    fn to_csv_header() -> &'static str {
-        "timestamp,battery_percent,speed_kmh,avg_speed_kmh,trip_m,total_m,frame_temp_c,uptime_s,voltage_v,current_a,capacity_mah,batt_temp_1_c,batt_temp_2_c,range_km"
-    }
-    
-    fn to_csv_row(&self) -> String {
-        format!(
-            "{},{},{:.1},{:.1},{},{},{:.1},{},{:.2},{:.2},{},{},{},{:.1}",
-            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
-            self.battery_percent,
-            self.speed_kmh,
-            self.avg_speed_kmh,
-            self.trip_m,
-            self.total_m,
-            self.frame_temp,
-            self.uptime_s,
-            self.voltage,
-            self.current,
-            self.capacity,
-            self.batt_temp_1,
-            self.batt_temp_2,
-            self.range_km
-        )
-    }
-}
-
 enum Command {
     Quit,
     Help,
@@ -143,35 +98,13 @@ fn print_help() {
     println!("╚══════════════════════════════════════════════════════════════╝\n");
 }
 
-async fn read_status(session: &mut MiSession) -> Result<ScooterStatus> {
-    let motor = session.motor_info().await?;
-    let battery = session.battery_info().await?;
-    let range = session.distance_left().await.unwrap_or(0.0);
-    
-    Ok(ScooterStatus {
-        timestamp: Local::now(),
-        battery_percent: motor.battery_percent,
-        speed_kmh: motor.speed_kmh,
-        avg_speed_kmh: motor.speed_average_kmh,
-        trip_m: motor.trip_distance_m,
-        total_m: motor.total_distance_m,
-        frame_temp: motor.frame_temperature,
-        uptime_s: motor.uptime.as_secs(),
-        voltage: battery.voltage,
-        current: battery.current,
-        capacity: battery.capacity,
-        batt_temp_1: battery.temperature_1,
-        batt_temp_2: battery.temperature_2,
-        range_km: range,
-    })
-}
-
-fn print_status(status: &ScooterStatus, logging: bool, interval: u64) {
+fn print_status(status: &Telemetry, logging: bool, interval: u64) {
     print!("\x1B[2J\x1B[1;1H"); // Clear screen
     println!("╔══════════════════════════════════════════════════════════════╗");
     println!("║       M365 Scooter Controller - {}        ║", status.timestamp.format("%Y-%m-%d %H:%M:%S"));
     println!("╠══════════════════════════════════════════════════════════════╣");
-    println!("║  🔋 Battery:     {:>3}%          📍 Range:    {:>5.1} km        ║", status.battery_percent, status.range_km);
+    let range = status.range_km.map(|range| format!("{:>5.1}", range)).unwrap_or_else(|| "  ---".to_string());
+    println!("║  🔋 Battery:     {:>3}%          📍 Range:    {} km        ║", status.battery_percent, range);
     println!("║  🚀 Speed:       {:>5.1} km/h     📊 Avg:      {:>5.1} km/h      ║", status.speed_kmh, status.avg_speed_kmh);
     println!("║  📍 Trip:        {:>6} m       🛣️  Total:    {:>6.1} km       ║", status.trip_m, status.total_m as f32 / 1000.0);
     println!("╠══════════════════════════════════════════════════════════════╣");
@@ -189,12 +122,14 @@ fn print_status(status: &ScooterStatus, logging: bool, interval: u64) {
 async fn load_token() -> Result<AuthToken> {
     let path = std::path::Path::new(".mi-token");
     let token = tokio::fs::read(path).await?;
-    Ok(token.try_into().expect("Invalid token length"))
+    Ok(ninebot_ble::mi_crypto::load_token_v1(&token)?)
 }
 
 async fn login(device: &btleplug::platform::Peripheral, token: &AuthToken) -> Result<MiSession> {
     let mut login = LoginRequest::new(device, token).await?;
-    let session = login.start().await?;
+    let mut session = login.start().await?;
+    // settings are read back, so "Done" means scooter applied them
+    session.set_verify_writes(true);
     Ok(session)
 }
 
@@ -212,7 +147,7 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid MAC address");
+    let mac = parse_mac(&args[1]).expect("Invalid MAC address");
     println!("🔍 Searching for scooter: {}", mac);
 
     // Load token
@@ -220,12 +155,11 @@ async fn main() -> Result<()> {
     println!("🔑 Token loaded");
 
     // Find and connect
-    let mut scanner = ScooterScanner::new().await?;
-    let scooter = scanner.wait_for(&mac).await?;
-    let device = scanner.peripheral(&scooter).await?;
+    let scanner = ScooterScanner::new().await?;
+    let device = scanner.connect_known(&mac).await?;
 
     println!("📶 Found scooter, connecting...");
-    let connection = ConnectionHelper::new(&device);
+    let connection = ConnectionHelper::with_adapter(&device, &scanner.central);
     connection.reconnect().await?;
 
     println!("🔐 Logging in...");
@@ -254,7 +188,8 @@ async fn main() -> Result<()> {
     let mut logging = false;
     let mut log_file: Option<std::fs::File> = None;
     let mut interval_secs: u64 = 1;
-    let mut last_status: Option<ScooterStatus> = None;
+    let mut last_status: Option<Telemetry> = None;
+    let mut continuity = SessionContinuity::default();
 
     // Main loop
     let mut interval = time::interval(Duration::from_secs(interval_secs));
@@ -262,11 +197,15 @@ async fn main() -> Result<()> {
     while running.load(Ordering::Relaxed) {
         tokio::select! {
             _ = interval.tick() => {
-                match read_status(&mut session).await {
+                match session.telemetry().await {
                     Ok(status) => {
                         // Log to file if enabled
+                        let follows = continuity.update(&status);
                         if logging {
                             if let Some(ref mut file) = log_file {
+                                if let Some(marker) = follows.csv_marker() {
+                                    writeln!(file, "{}", marker).ok();
+                                }
                                 writeln!(file, "{}", status.to_csv_row()).ok();
                             }
                         }
@@ -276,13 +215,15 @@ async fn main() -> Result<()> {
                     }
                     Err(e) => {
                         eprintln!("\n⚠️  Read error: {}. Attempting reconnect...", e);
+                        session.unsubscribe().await.ok();
                         if let Err(re) = connection.reconnect().await {
                             eprintln!("❌ Reconnect failed: {}", re);
                             break;
                         }
-                        match login(&device, &token).await {
+                        match login(&connection.device(), &token).await {
                             Ok(new_session) => {
                                 session = new_session;
+                                continuity.reconnected();
                                 println!("✅ Reconnected!");
                             }
                             Err(le) => {
@@ -336,7 +277,7 @@ async fn main() -> Result<()> {
                             let filename = format!("scooter_log_{}.csv", Local::now().format("%Y%m%d_%H%M%S"));
                             match std::fs::File::create(&filename) {
                                 Ok(mut file) => {
-                                    writeln!(file, "{}", ScooterStatus::to_csv_header()).ok();
+                                    writeln!(file, "{}", Telemetry::csv_header()).ok();
                                     log_file = Some(file);
                                     logging = true;
                                     println!("\n📝 Started logging to: {}", filename);
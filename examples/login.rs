@@ -1,5 +1,4 @@
 use anyhow::Result;
-use btleplug::api::{BDAddr};
 use tracing_subscriber;
 use tokio::io::AsyncReadExt;
 use std::path::Path;
@@ -8,13 +7,22 @@ use tracing::Level;
 use std::env;
 use tracing_subscriber::fmt::format::FmtSpan;
 use ninebot_ble::{
+  parse_mac,
   AuthToken,
   ScooterScanner,
   LoginRequest,
-  ConnectionHelper
+  ConnectionHelper,
+  TokenStore
 };
+use btleplug::api::BDAddr;
+
+async fn load_token(mac: &BDAddr) -> Result<Option<AuthToken>> {
+  let store = TokenStore::load(".mi-tokens")?;
+  if let Some(token) = store.get(mac) {
+    tracing::debug!("Using token for {} from .mi-tokens", mac);
+    return Ok(Some(*token));
+  }
 
-async fn load_token() -> Result<Option<AuthToken>> {
   let path = Path::new(".mi-token");
   if !path.exists() {
     tracing::warn!("No .mi-token file found");
@@ -42,13 +50,14 @@ async fn main() -> Result<()>{
     panic!("First argument is scooter mac address");
   }
 
-  let token = load_token().await?;
+  let mac = parse_mac(&args[1]).expect("Invalid mac address");
+
+  let token = load_token(&mac).await?;
   let Some(token) = token else {
     println!("No .mi-token found. Run `cargo run --example register {} ` first.", args[1]);
     return Ok(());
   };
 
-  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
   tracing::info!("Searching scooter with address: {}", mac);
 
   let mut scanner = ScooterScanner::new().await?;
@@ -6,24 +6,13 @@ use btleplug::platform::Manager;
 use uuid::Uuid;
 use futures::stream::StreamExt;
 use futures::FutureExt;
+use ninebot_ble::mi_crypto::MiAuthStateMachine;
+use ninebot_ble::protocol::MiProtocol;
 
 // FE95 Service Characteristics (discovered from your device)
 const UPNP_UUID: Uuid = Uuid::from_u128(0x00000010_0000_1000_8000_00805f9b34fb);  // TX
 const AVDTP_UUID: Uuid = Uuid::from_u128(0x00000019_0000_1000_8000_00805f9b34fb); // RX
 
-// MiAuth Commands
-const CMD_GET_INFO: [u8; 4] = [0xA2, 0x00, 0x00, 0x00];
-const CMD_SET_KEY: [u8; 4] = [0x15, 0x00, 0x00, 0x00];
-const CMD_AUTH: [u8; 4] = [0x13, 0x00, 0x00, 0x00];
-const CMD_LOGIN: [u8; 4] = [0x24, 0x00, 0x00, 0x00];
-
-// Expected responses
-const RCV_SEND_DID: [u8; 6] = [0x00, 0x00, 0x00, 0x00, 0x02, 0x00];
-#[allow(dead_code)]
-const RCV_RDY: [u8; 4] = [0x00, 0x00, 0x01, 0x01];
-#[allow(dead_code)]
-const RCV_OK: [u8; 4] = [0x00, 0x00, 0x01, 0x00];
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     tracing_subscriber::fmt::init();
@@ -58,15 +47,48 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("[3] Discovering services...");
     device.discover_services().await?;
 
+    // === MiAuth Handshake Sequence ===
+    // Registration flow, press power button on scooter when asked to
+    println!("=== Starting MiAuth Handshake (press power button when scooter beeps) ===\n");
+
+    let mut protocol = MiProtocol::new(&device).await?;
+    let mut machine = MiAuthStateMachine::register();
+    let mut next = Some(machine.start());
+    let mut step = 1;
+
+    while let Some(cmd) = next {
+        println!("--- Step {}: {:?} ---", step, cmd);
+        let response = match protocol.execute_auth_cmd(&cmd).await {
+            Ok(response) => response,
+            Err(e) => {
+                println!("    Failed: {}", e);
+                break;
+            }
+        };
+        println!("<<< {:02X?}", response);
+
+        next = match machine.step(&response) {
+            Ok(next) => next,
+            Err(e) => {
+                println!("    Handshake stopped: {}", e);
+                break;
+            }
+        };
+        step += 1;
+    }
+
+    match machine.token() {
+        Some(token) => println!("\n✓ MiAuth finished, token: {:02X?}\n", token),
+        None => println!("\n✗ MiAuth did not finish\n"),
+    }
+    protocol.dispose().await?;
+
     let chars = device.characteristics();
     let upnp = chars.iter().find(|c| c.uuid == UPNP_UUID).expect("UPNP char not found");
     let avdtp = chars.iter().find(|c| c.uuid == AVDTP_UUID).expect("AVDTP char not found");
 
-    // Subscribe to BOTH notification characteristics
     device.subscribe(upnp).await?;
     device.subscribe(avdtp).await?;
-    println!("[4] Subscribed to UPNP and AVDTP\n");
-
     let mut notification_stream = device.notifications().await?;
 
     // Helper to send and wait for response
@@ -100,51 +122,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    // === MiAuth Handshake Sequence ===
-    println!("=== Starting MiAuth Handshake ===\n");
-
-    // Step 1: Send CMD_GET_INFO to UPNP, expect response on AVDTP
-    println!("--- Step 1: Get Info ---");
-    let resp = send_and_wait(&device, upnp, &CMD_GET_INFO, "CMD_GET_INFO", &mut notification_stream).await;
-    
-    if let Some(r) = &resp {
-        if r.as_slice() == RCV_SEND_DID {
-            println!("✓ Device wants us to send DID (Device ID)\n");
-        } else {
-            println!("? Unknown response, continuing...\n");
-        }
-    }
-
-    // Step 2: Try sending a fake DID
-    // Format: CMD_SEND_DID header + DID bytes
-    // Typical DID is a 20-byte string
-    println!("--- Step 2: Send DID ---");
-    let fake_did = b"did:12345678901234567890";
-    let mut did_packet = vec![0x00, 0x00, 0x00, 0x00, 0x02, 0x00]; // CMD_SEND_DID header
-    did_packet.extend_from_slice(fake_did);
-    let _ = send_and_wait(&device, upnp, &did_packet, "CMD_SEND_DID", &mut notification_stream).await;
-
-    // Step 3: Try CMD_SET_KEY
-    println!("--- Step 3: Set Key ---");
-    let _ = send_and_wait(&device, upnp, &CMD_SET_KEY, "CMD_SET_KEY", &mut notification_stream).await;
-
-    // Step 4: Generate and send ECDH public key
-    // For testing, we'll send random bytes as a "key"
-    println!("--- Step 4: Send Public Key ---");
-    let fake_pubkey: Vec<u8> = (0..64).map(|i| i as u8).collect();
-    let mut key_packet = vec![0x00, 0x00, 0x00, 0x0b, 0x01, 0x00]; // CMD_SEND_KEY header
-    key_packet.extend_from_slice(&fake_pubkey);
-    let _ = send_and_wait(&device, upnp, &key_packet, "CMD_SEND_KEY (fake)", &mut notification_stream).await;
-
-    // Step 5: Try AUTH command
-    println!("--- Step 5: Auth ---");
-    let _ = send_and_wait(&device, upnp, &CMD_AUTH, "CMD_AUTH", &mut notification_stream).await;
-
-    // Step 6: Try LOGIN command
-    println!("--- Step 6: Login ---");
-    let _ = send_and_wait(&device, upnp, &CMD_LOGIN, "CMD_LOGIN", &mut notification_stream).await;
-
-    // Step 7: Now try UART commands
+    // Now try UART commands
     println!("\n=== Testing UART after handshake ===\n");
     
     // Try Xiaomi protocol commands on both UPNP and the NUS TX
@@ -1,12 +1,12 @@
 use std::io::{self, Write};
 use std::time::Duration;
 use tokio::time;
-use btleplug::api::BDAddr;
 use anyhow::Result;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 
 use ninebot_ble::{
+    parse_mac,
     ScooterScanner, ConnectionHelper, LoginRequest, session::MiSession,
     AuthToken
 };
@@ -14,7 +14,7 @@ use ninebot_ble::{
 async fn load_token() -> Result<AuthToken> {
     let path = std::path::Path::new(".mi-token");
     let token = tokio::fs::read(path).await?;
-    Ok(token.try_into().expect("Invalid token length"))
+    Ok(ninebot_ble::mi_crypto::load_token_v1(&token)?)
 }
 
 async fn print_status(session: &mut MiSession) -> Result<()> {
@@ -89,24 +89,29 @@ async fn main() -> Result<()> {
 
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        panic!("Usage: monitor <MAC_ADDRESS>");
+        panic!("Usage: monitor <MAC_ADDRESS | SCOOTER_NAME>");
     }
 
-    let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid MAC address");
-    println!("🔍 Searching for scooter: {}", mac);
-
     // Load token
     let token = load_token().await?;
     println!("🔑 Token loaded");
 
-    // Find and connect to scooter
+    // Find and connect to scooter, argument which is not a MAC is taken as scooter name
     let mut scanner = ScooterScanner::new().await?;
-    let scooter = scanner.wait_for(&mac).await?;
-    let device = scanner.peripheral(&scooter).await?;
+    let mac = match parse_mac(&args[1]) {
+        Ok(mac) => mac,
+        Err(_) => {
+            println!("🔍 Searching for scooter named: {}", args[1]);
+            scanner.wait_for_name(&args[1], Duration::from_secs(30)).await?.addr
+        }
+    };
+    println!("🔍 Searching for scooter: {}", mac);
+
+    let device = scanner.connect_known(&mac).await?;
 
     println!("📶 Found scooter, connecting...");
 
-    let connection = ConnectionHelper::new(&device);
+    let connection = ConnectionHelper::with_adapter(&device, &scanner.central);
     connection.reconnect().await?;
 
     println!("🔐 Logging in...");
@@ -126,24 +131,13 @@ async fn main() -> Result<()> {
         if let Err(e) = print_status(&mut session).await {
             eprintln!("Error reading status: {}", e);
             
-            // Try to reconnect
+            // Resume keeps session keys when link survived, otherwise connects and logs in again
             println!("🔄 Attempting to reconnect...");
-            if let Err(e) = connection.reconnect().await {
+            if let Err(e) = session.resume().await {
                 eprintln!("❌ Reconnection failed: {}", e);
                 break;
             }
-            
-            // Re-login
-            match login(&device, &token).await {
-                Ok(new_session) => {
-                    session = new_session;
-                    println!("✅ Reconnected!");
-                }
-                Err(e) => {
-                    eprintln!("❌ Re-login failed: {}", e);
-                    break;
-                }
-            }
+            println!("✅ Reconnected!");
         }
     }
 
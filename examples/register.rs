@@ -2,7 +2,6 @@ use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 
 use btleplug::platform::{Peripheral};
-use btleplug::api::BDAddr;
 use tokio::io::{BufWriter, AsyncWriteExt};
 use tokio::fs::File;
 use pretty_hex::*;
@@ -12,10 +11,12 @@ use std::path::Path;
 use anyhow::Result;
 
 use ninebot_ble::{
+  parse_mac,
   ScooterScanner, ScannerEvent,
   RegistrationRequest, RegistrationError,
-  ConnectionHelper, AuthToken
+  ConnectionHelper, AuthToken, TokenStore
 };
+use btleplug::api::BDAddr;
 
 async fn save_token(token : &AuthToken) -> Result<()> {
   let path = Path::new(".mi-token");
@@ -29,7 +30,16 @@ async fn save_token(token : &AuthToken) -> Result<()> {
   Ok(())
 }
 
-async fn register(device: &Peripheral) -> Result<()> {
+fn store_token(addr: &BDAddr, token : &AuthToken) -> Result<()> {
+  let path = Path::new(".mi-tokens");
+  let mut store = TokenStore::load(path)?;
+  store.put(*addr, *token);
+  store.save(path)?;
+  tracing::info!("Token for {} stored in {:?}", addr, path);
+  Ok(())
+}
+
+async fn register(addr: &BDAddr, device: &Peripheral) -> Result<()> {
   let connection = ConnectionHelper::new(&device);
   let mut retry_count = 0;
   const MAX_RETRIES: u32 = 5;
@@ -66,7 +76,11 @@ async fn register(device: &Peripheral) -> Result<()> {
     match request.start().await {
       Ok(token) => {
         tracing::info!("✅ Registration successful!");
+        if let Some(device_id) = request.device_id() {
+          tracing::info!("Scooter device id: {}", device_id);
+        }
         save_token(&token).await?;
+        store_token(addr, &token)?;
         break;
       },
       Err(RegistrationError::RestartNeeded) => {
@@ -96,7 +110,7 @@ async fn main() -> Result<()> {
     panic!("First argument is scooter mac address");
   }
 
-  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
+  let mac = parse_mac(&args[1]).expect("Invalid mac address");
   tracing::info!("Searching scooter with address: {}", mac);
 
   let mut scanner = ScooterScanner::new().await?;
@@ -108,7 +122,7 @@ async fn main() -> Result<()> {
         if scooter.addr == mac {
           tracing::info!("Found your scooter, starting registration");
           let device = scanner.peripheral(&scooter).await?;
-          register(&device).await?;
+          register(&mac, &device).await?;
           break;
         } else {
           tracing::info!("Found scooter nearby: {} with mac: {}", scooter.name.unwrap(), scooter.addr);
@@ -1,5 +1,4 @@
 use anyhow::{Result, Context};
-use btleplug::api::{BDAddr};
 use tracing_subscriber;
 use tokio::io::AsyncReadExt;
 use std::path::Path;
@@ -11,6 +10,7 @@ use tokio::time;
 use std::time::Duration;
 
 use ninebot_ble::{
+  parse_mac,
   AuthToken,
   ScooterScanner,
   LoginRequest,
@@ -45,7 +45,7 @@ async fn main() -> Result<()>{
   let token = load_token().await
     .with_context(|| "Could not load registration token")?;
 
-  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
+  let mac = parse_mac(&args[1]).expect("Invalid mac address");
   tracing::info!("Searching scooter with address: {}", mac);
 
   let mut scanner = ScooterScanner::new().await?;
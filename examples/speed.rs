@@ -1,5 +1,4 @@
 use anyhow::Result;
-use btleplug::api::{BDAddr};
 use tracing_subscriber;
 use tokio::io::AsyncReadExt;
 use std::path::Path;
@@ -10,6 +9,7 @@ use tokio::time;
 use std::time::Duration;
 use tracing_subscriber::fmt::format::FmtSpan;
 use ninebot_ble::{
+  parse_mac,
   AuthToken,
   ScooterScanner,
   LoginRequest,
@@ -50,7 +50,7 @@ async fn main() -> Result<()>{
 
   let token = load_token().await?;
 
-  let mac = BDAddr::from_str_delim(&args[1]).expect("Invalid mac address");
+  let mac = parse_mac(&args[1]).expect("Invalid mac address");
   tracing::info!("Searching scooter with address: {}", mac);
 
   let mut scanner = ScooterScanner::new().await?;
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use uuid::Uuid;
+
+const XIAOMI_SERVICE_UUID : Uuid = Uuid::from_u128(0x0000fe95_0000_1000_8000_00805f9b34fb);
+
+/**
+ * Bluetooth SIG company identifier of Xiaomi, some clones put MiBeacon frame in manufacturer data instead of FE95 service data
+ */
+const XIAOMI_COMPANY_ID : u16 = 0x038F;
+
+/**
+ * MiBeacon frame control bits
+ */
+const FRAME_ENCRYPTED : u16         = 0x0008;
+const FRAME_HAS_MAC : u16           = 0x0010;
+const FRAME_HAS_CAPABILITY : u16    = 0x0020;
+const FRAME_HAS_OBJECT : u16        = 0x0040;
+const CAPABILITY_HAS_IO : u8        = 0x20;
+
+/**
+ * MiBeacon object with battery level in percents, single byte
+ */
+const OBJECT_BATTERY : u16 = 0x100A;
+
+/**
+ * Telemetry broadcasted by scooter in advertisement, readable without connecting or logging in
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PassiveTelemetry {
+  pub product_id: u16,
+  pub frame_counter: u8,
+  pub battery_percent: u8,
+}
+
+impl PassiveTelemetry {
+  /**
+   * Parse telemetry from advertisement. FE95 service data is checked first, then Xiaomi manufacturer data.
+   * Returns None when device does not broadcast anything we understand
+   */
+  pub fn from_advertisement(service_data: &HashMap<Uuid, Vec<u8>>, manufacturer_data: &HashMap<u16, Vec<u8>>) -> Option<Self> {
+    service_data.get(&XIAOMI_SERVICE_UUID)
+      .and_then(|frame| Self::from_mibeacon(frame))
+      .or_else(|| manufacturer_data.get(&XIAOMI_COMPANY_ID).and_then(|frame| Self::from_mibeacon(frame)))
+  }
+
+  /**
+   * Parse unencrypted MiBeacon frame:
+   * frame control (u16 LE), product id (u16 LE), frame counter, [mac (6)], [capability (1 + 2 for IO)], [objects]
+   * Each object is: id (u16 LE), length, value
+   */
+  pub fn from_mibeacon(frame: &[u8]) -> Option<Self> {
+    if frame.len() < 5 {
+      return None
+    }
+
+    let frame_control = u16::from_le_bytes([frame[0], frame[1]]);
+    let product_id = u16::from_le_bytes([frame[2], frame[3]]);
+    let frame_counter = frame[4];
+
+    if frame_control & FRAME_ENCRYPTED != 0 || frame_control & FRAME_HAS_OBJECT == 0 {
+      tracing::debug!("MiBeacon without readable objects: {:04x}", frame_control);
+      return None
+    }
+
+    let mut offset = 5;
+    if frame_control & FRAME_HAS_MAC != 0 {
+      offset += 6;
+    }
+    if frame_control & FRAME_HAS_CAPABILITY != 0 {
+      let capability = *frame.get(offset)?;
+      offset += 1;
+      if capability & CAPABILITY_HAS_IO != 0 {
+        offset += 2;
+      }
+    }
+
+    while offset + 3 <= frame.len() {
+      let object_id = u16::from_le_bytes([frame[offset], frame[offset + 1]]);
+      let length = frame[offset + 2] as usize;
+      let value = frame.get(offset + 3..offset + 3 + length)?;
+
+      if object_id == OBJECT_BATTERY && length == 1 {
+        return Some(Self { product_id, frame_counter, battery_percent: value[0] })
+      }
+
+      offset += 3 + length;
+    }
+
+    None
+  }
+}
@@ -3,9 +3,9 @@ use jni::JNIEnv;
 #[cfg(target_os = "android")]
 use jni::objects::{JClass, JString};
 #[cfg(target_os = "android")]
-use jni::sys::{jstring};
+use jni::sys::{jstring, jbyteArray};
 #[cfg(target_os = "android")]
-use std::sync::{Mutex};
+use tokio::sync::Mutex;
 #[cfg(target_os = "android")]
 use once_cell::sync::Lazy;
 #[cfg(target_os = "android")]
@@ -15,23 +15,76 @@ use crate::scanner::ScooterScanner;
 #[cfg(target_os = "android")]
 use crate::session::MiSession;
 #[cfg(target_os = "android")]
-use btleplug::api::BDAddr;
-#[cfg(target_os = "android")]
-use std::str::FromStr;
+use crate::scanner::parse_mac;
 #[cfg(target_os = "android")]
 use crate::login::LoginRequest;
 #[cfg(target_os = "android")]
-use crate::mi_crypto::AuthToken;
+use crate::mi_crypto::{AuthToken, import_token};
 #[cfg(target_os = "android")]
 use hex;
+#[cfg(target_os = "android")]
+use jni::sys::jint;
+#[cfg(target_os = "android")]
+use btleplug::platform::Peripheral;
+#[cfg(target_os = "android")]
+use crate::connection::ConnectionHelper;
+#[cfg(target_os = "android")]
+use crate::consts::Registers;
+#[cfg(target_os = "android")]
+use std::sync::atomic::{AtomicU32, Ordering};
 
 #[cfg(target_os = "android")]
 static RUNTIME: Lazy<Runtime> = Lazy::new(|| Runtime::new().unwrap());
 #[cfg(target_os = "android")]
 static SCANNER: Lazy<Mutex<Option<ScooterScanner>>> = Lazy::new(|| Mutex::new(None));
+// Tokio mutex, guards are held across awaits of session commands
 #[cfg(target_os = "android")]
 pub static SESSION: Lazy<Mutex<Option<MiSession>>> = Lazy::new(|| Mutex::new(None));
 
+// Android BLE auth frequently fails on first attempt, so login is retried after reconnecting
+#[cfg(target_os = "android")]
+const DEFAULT_LOGIN_ATTEMPTS: u32 = 3;
+#[cfg(target_os = "android")]
+static LOGIN_ATTEMPTS: AtomicU32 = AtomicU32::new(DEFAULT_LOGIN_ATTEMPTS);
+
+/// Log in to scooter, on failure reconnect and try again up to configured number of attempts.
+/// Progress is reported with `BleEvent::Status`, error of the last attempt is returned.
+#[cfg(target_os = "android")]
+pub(crate) async fn login_with_retry(peripheral: &Peripheral, token: &AuthToken) -> Result<MiSession, String> {
+    let attempts = LOGIN_ATTEMPTS.load(Ordering::Relaxed).max(1);
+    let mut last_error = String::new();
+
+    for attempt in 1..=attempts {
+        if attempt > 1 {
+            crate::send_status(&format!("Retrying authentication ({}/{})...", attempt, attempts)).await;
+            if let Err(e) = ConnectionHelper::new(peripheral).with_required_service(Some(Registers::AUTH.to_uuid())).reconnect().await {
+                log::warn!("Reconnect before login retry failed: {:?}", e);
+            }
+        }
+
+        let result = match LoginRequest::new(peripheral, token).await {
+            Ok(mut login_req) => login_req.start().await.map_err(|e| format!("Auth Failed: {}", e)),
+            Err(e) => Err(format!("Login Init Failed: {}", e))
+        };
+
+        match result {
+            Ok(session) => return Ok(session),
+            Err(e) => {
+                log::error!("Login attempt {}/{} failed: {}", attempt, attempts, e);
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub extern "system" fn Java_com_ninebot_ble_NativeLib_setLoginRetries(_env: JNIEnv, _: JClass, attempts: jint) {
+    LOGIN_ATTEMPTS.store(attempts.max(1) as u32, Ordering::Relaxed);
+}
+
 // Initialize btleplug on library load
 #[cfg(target_os = "android")]
 #[no_mangle]
@@ -54,7 +107,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_startScan(env: JNIEnv, _:
         let mut scanner = ScooterScanner::new().await.map_err(|e| e.to_string())?;
         let _rx = scanner.start().await.map_err(|e| e.to_string())?;
         
-        let mut global_scanner = SCANNER.lock().unwrap();
+        let mut global_scanner = SCANNER.lock().await;
         *global_scanner = Some(scanner);
         Ok::<(), String>(())
     });
@@ -69,7 +122,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_startScan(env: JNIEnv, _:
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getDevices(env: JNIEnv, _: JClass) -> jstring {
     let devices = RUNTIME.block_on(async {
-        let scanner_guard = SCANNER.lock().unwrap();
+        let scanner_guard = SCANNER.lock().await;
         if let Some(scanner) = scanner_guard.as_ref() {
             let devices = scanner.scooters().await;
             // Format as simple string for demo: "name,addr;name,addr"
@@ -93,16 +146,43 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_connect(
     _: JClass, 
     addr: JString,
 ) -> jstring {
+    // Without token only clones which skip authentication can be used
+    let token: AuthToken = [0u8; 12];
+    connect_with_token(env, addr, token)
+}
+
+/// Connect using token saved during registration (`.mi-token` contents, raw or hex encoded)
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub extern "system" fn Java_com_ninebot_ble_NativeLib_connectWithToken(
+    env: JNIEnv,
+    _: JClass,
+    addr: JString,
+    token: jbyteArray,
+) -> jstring {
+    let token = match env.convert_byte_array(token) {
+        Ok(bytes) => bytes,
+        Err(_) => return env.new_string("Error: Cannot get token").unwrap().into_inner()
+    };
+
+    match import_token(&token) {
+        Ok(token) => connect_with_token(env, addr, token),
+        Err(e) => env.new_string(format!("Invalid token: {}", e)).unwrap().into_inner()
+    }
+}
+
+#[cfg(target_os = "android")]
+fn connect_with_token(env: JNIEnv, addr: JString, token: AuthToken) -> jstring {
     // In jni 0.19, get_string returns JavaStr. We convert it to String.
     let addr_str: String = match env.get_string(addr) {
         Ok(s) => s.into(),
         Err(_) => return env.new_string("Error: Cannot get string").unwrap().into_inner()
     };
     
-    let result = RUNTIME.block_on(async {
-        let bd_addr = BDAddr::from_str(&addr_str).map_err(|e| format!("Invalid MAC: {}", e))?;
+    let result: Result<String, String> = RUNTIME.block_on(async {
+        let bd_addr = parse_mac(&addr_str).map_err(|e| e.to_string())?;
         
-        let scanner_guard = SCANNER.lock().unwrap();
+        let scanner_guard = SCANNER.lock().await;
         let scanner = scanner_guard.as_ref().ok_or("Scanner not initialized")?;
         
         let devices = scanner.devices().await;
@@ -110,21 +190,19 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_connect(
             .ok_or("Device not found in scan results")?;
             
         let peripheral = scanner.peripheral(target).await.map_err(|e| format!("Peripheral error: {}", e))?;
-        
-        let token: AuthToken = [0u8; 12]; // DUMMY TOKEN
-        
-        let mut login_req = LoginRequest::new(&peripheral, &token).await.map_err(|e| format!("Login init error: {}", e))?;
-        
-        match login_req.start().await {
-            Ok(session) => {
-                let mut session_guard = SESSION.lock().unwrap();
-                *session_guard = Some(session);
-                Ok("Connected and Logged In".to_string())
-            },
-            Err(e) => {
-                 Err(format!("Login failed: {}", e))
+
+        let old_session = SESSION.lock().await.take();
+        if let Some(old_session) = old_session {
+            if let Err(e) = old_session.close().await {
+                log::warn!("Could not close previous session: {}", e);
             }
         }
+
+        let session = login_with_retry(&peripheral, &token).await?;
+
+        let mut session_guard = SESSION.lock().await;
+        *session_guard = Some(session);
+        Ok("Connected and Logged In".to_string())
     });
 
     match result {
@@ -137,7 +215,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_connect(
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryVoltage(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.battery_voltage().await
                 .map(|voltage| format!("{:.2}", voltage))
@@ -153,11 +231,49 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryVoltage(env: JNI
     }
 }
 
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub extern "system" fn Java_com_ninebot_ble_NativeLib_getFirmwareVersion(env: JNIEnv, _: JClass) -> jstring {
+    let result: Result<String, String> = RUNTIME.block_on(async {
+        let mut session_guard = SESSION.lock().await;
+        if let Some(ref mut session) = *session_guard {
+            session.firmware_version().await
+                .map_err(|e| format!("Firmware version error: {}", e))
+        } else {
+            Err("No active session".to_string())
+        }
+    });
+
+    match result {
+        Ok(version) => env.new_string(version).unwrap().into_inner(),
+        Err(e) => env.new_string(e).unwrap().into_inner()
+    }
+}
+
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub extern "system" fn Java_com_ninebot_ble_NativeLib_getSerialNumber(env: JNIEnv, _: JClass) -> jstring {
+    let result: Result<String, String> = RUNTIME.block_on(async {
+        let mut session_guard = SESSION.lock().await;
+        if let Some(ref mut session) = *session_guard {
+            session.serial_number().await
+                .map_err(|e| format!("Serial number error: {}", e))
+        } else {
+            Err("No active session".to_string())
+        }
+    });
+
+    match result {
+        Ok(serial) => env.new_string(serial).unwrap().into_inner(),
+        Err(e) => env.new_string(e).unwrap().into_inner()
+    }
+}
+
 #[cfg(target_os = "android")]
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryAmperage(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.battery_amperage().await
                 .map(|amperage| format!("{:.2}", amperage))
@@ -177,7 +293,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryAmperage(env: JN
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryPercentage(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.battery_percentage().await
                 .map(|percentage| format!("{:.0}", percentage))
@@ -197,7 +313,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryPercentage(env:
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryInfo(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.battery_info().await
                 .map(|info| format!(
@@ -220,7 +336,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getBatteryInfo(env: JNIEnv
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getCurrentSpeed(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.motor_info().await
                 .map(|info| format!("{:.2}", info.speed_kmh))
@@ -240,7 +356,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getCurrentSpeed(env: JNIEn
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getAverageSpeed(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.motor_info().await
                 .map(|info| format!("{:.2}", info.speed_average_kmh))
@@ -260,7 +376,7 @@ pub extern "system" fn Java_com_ninebot_ble_NativeLib_getAverageSpeed(env: JNIEn
 #[no_mangle]
 pub extern "system" fn Java_com_ninebot_ble_NativeLib_getMotorInfo(env: JNIEnv, _: JClass) -> jstring {
     let result: Result<String, String> = RUNTIME.block_on(async {
-        let mut session_guard = SESSION.lock().unwrap();
+        let mut session_guard = SESSION.lock().await;
         if let Some(ref mut session) = *session_guard {
             session.motor_info().await
                 .map(|info| format!(
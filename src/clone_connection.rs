@@ -1,11 +1,15 @@
 use std::collections::BTreeSet;
+use std::sync::Mutex;
 use btleplug::api::{Characteristic, Peripheral as _, WriteType, CharPropFlags};
 use btleplug::platform::Peripheral;
 use uuid::Uuid;
 use futures::stream::StreamExt;
 use std::time::Duration;
 use tokio::time;
-use anyhow::{Result, anyhow, Context};
+use anyhow::{Result, anyhow};
+use crate::protocol::{FrameAssembler, Protocol};
+use crate::ninebot::Address;
+use crate::util::with_timeout;
 
 // Service UUIDs
 const _NUS_SERVICE_UUID: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
@@ -23,44 +27,98 @@ pub struct ScooterConnection {
     device: Peripheral,
     tx_char: Characteristic,
     rx_char: Characteristic,
+    protocol: Mutex<Protocol>,
     #[allow(dead_code)]
     is_m365: bool,
 }
 
 impl ScooterConnection {
     pub async fn connect(device: &Peripheral, is_m365: bool) -> Result<Self> {
+        Self::connect_with(device, is_m365, false).await
+    }
+
+    /// Same as `connect`, but trusts characteristics cached from earlier connection and skips
+    /// the discovery delay. Falls back to discovery when cached ones are missing or don't fit,
+    /// so it is safe to use for every reconnect to the same scooter.
+    pub async fn connect_fast(device: &Peripheral, is_m365: bool) -> Result<Self> {
+        Self::connect_with(device, is_m365, true).await
+    }
+
+    async fn connect_with(device: &Peripheral, is_m365: bool, skip_discovery: bool) -> Result<Self> {
         if !device.is_connected().await? {
             device.connect().await?;
         }
-        
-        // Wait for services to be discovered
-        time::sleep(Duration::from_secs(2)).await;
-        device.discover_services().await?;
 
-        let chars = device.characteristics();
-        
-        let (tx, rx) = if is_m365 {
-            Self::find_m365_characteristics(&chars)
-        } else {
-            Self::find_characteristics(&chars)
+        let mut chars = device.characteristics();
+        let mut selected = if skip_discovery { Self::select_characteristics(&chars, is_m365) } else { None };
+
+        if selected.is_none() {
+            if skip_discovery {
+                println!("No usable cached characteristics, discovering services");
+            }
+            // Wait for services to be discovered
+            time::sleep(Duration::from_secs(2)).await;
+            device.discover_services().await?;
+
+            chars = device.characteristics();
+            selected = Self::select_characteristics(&chars, is_m365);
         }
-        .ok_or_else(|| anyhow!("Could not find compatible UART characteristics"))?;
+
+        let (tx, rx) = selected
+            .ok_or_else(|| anyhow!("Could not find compatible UART characteristics"))?;
 
         println!("Selected characteristics: TX={:?}, RX={:?}", tx.uuid, rx.uuid);
         println!("M365 mode: {}", is_m365);
 
-        // Subscribe to notifications
-        device.subscribe(&rx).await
-            .context("Failed to subscribe to notification characteristic")?;
+        // Subscribe to notifications, falling back to other notify characteristics
+        let rx = Self::subscribe_notify(device, &chars, rx).await?;
 
         Ok(Self {
             device: device.clone(),
             tx_char: tx,
             rx_char: rx,
+            protocol: Mutex::new(Protocol::Xiaomi),
             is_m365,
         })
     }
 
+    /// Subscribes to the selected notify characteristic. Some clones expose several
+    /// notify characteristics and the first match is not subscribable, so the other
+    /// candidates (FE95 ones first) are tried before giving up.
+    async fn subscribe_notify(device: &Peripheral, chars: &BTreeSet<Characteristic>, rx: Characteristic) -> Result<Characteristic> {
+        let mut candidates: Vec<&Characteristic> = chars.iter()
+            .filter(|c| c.uuid != rx.uuid)
+            .filter(|c| c.properties.contains(CharPropFlags::NOTIFY) || c.properties.contains(CharPropFlags::INDICATE))
+            .collect();
+        candidates.sort_by_key(|c| c.service_uuid != MI_SERVICE_UUID);
+
+        let mut tried = Vec::new();
+        for candidate in std::iter::once(&rx).chain(candidates) {
+            match device.subscribe(candidate).await {
+                Ok(_) => {
+                    if candidate.uuid != rx.uuid {
+                        println!("Subscribed to fallback notify characteristic {:?}", candidate.uuid);
+                    }
+                    return Ok(candidate.clone());
+                },
+                Err(err) => {
+                    println!("Failed to subscribe to {:?}: {}", candidate.uuid, err);
+                    tried.push(candidate.uuid.to_string());
+                }
+            }
+        }
+
+        Err(anyhow!("Failed to subscribe to any notification characteristic, tried: {}", tried.join(", ")))
+    }
+
+    fn select_characteristics(chars: &BTreeSet<Characteristic>, is_m365: bool) -> Option<(Characteristic, Characteristic)> {
+        if is_m365 {
+            Self::find_m365_characteristics(chars)
+        } else {
+            Self::find_characteristics(chars)
+        }
+    }
+
     fn find_characteristics(chars: &BTreeSet<Characteristic>) -> Option<(Characteristic, Characteristic)> {
         // 1. Try Standard NUS
         let nus_tx = chars.iter().find(|c| c.uuid == NUS_TX_UUID);
@@ -132,30 +190,54 @@ impl ScooterConnection {
         Ok(())
     }
 
+    /// Waits for a response frame. Frames longer than one notification are joined
+    /// using the length from their header; notifications with an unknown header are
+    /// returned as they are.
     pub async fn read_response(&self, timeout_duration: Duration) -> Result<Vec<u8>> {
         let mut notification_stream = self.device.notifications().await?;
-        
-        let timeout = time::sleep(timeout_duration);
-        tokio::pin!(timeout);
-
-        loop {
-            tokio::select! {
-                Some(data) = notification_stream.next() => {
-                    if data.uuid == self.rx_char.uuid {
-                        return Ok(data.value);
+        let mut assembler = FrameAssembler::new();
+
+        let frame = with_timeout(async {
+            while let Some(data) = notification_stream.next().await {
+                if data.uuid == self.rx_char.uuid {
+                    if let Some(frame) = assembler.push(&data.value) {
+                        return Some(frame);
+                    }
+                    if assembler.expected_len().is_none() {
+                        return Some(assembler.take());
                     }
-                }
-                _ = &mut timeout => {
-                    return Err(anyhow!("Timeout waiting for response"));
                 }
             }
-        }
+            None
+        }, timeout_duration).await?;
+
+        frame.ok_or_else(|| anyhow!("Notification stream ended while waiting for response"))
     }
 
-    /// Sends a command and waits for a response
+    /// Framing used for commands, Xiaomi until a response in other framing is received
+    pub fn protocol(&self) -> Protocol {
+        *self.protocol.lock().unwrap()
+    }
+
+    /// Force framing, e.g. when the variant is known upfront
+    pub fn set_protocol(&self, protocol: Protocol) {
+        *self.protocol.lock().unwrap() = protocol;
+    }
+
+    /// Sends a command and waits for a response. Framing of a valid response is
+    /// remembered, so later commands are sent the way the scooter answers.
     pub async fn transaction(&self, payload: &[u8]) -> Result<Vec<u8>> {
         self.send_command(payload).await?;
-        self.read_response(Duration::from_secs(2)).await
+        let response = self.read_response(Duration::from_secs(2)).await?;
+
+        if let Some(detected) = Protocol::detect(&response) {
+            if detected != self.protocol() {
+                println!("Detected {:?} framing, switching", detected);
+                self.set_protocol(detected);
+            }
+        }
+
+        Ok(response)
     }
 
     /// Tries to read the firmware version to verify connection
@@ -196,25 +278,38 @@ impl ScooterConnection {
     }
 
     fn build_packet(&self, payload: &[u8]) -> Vec<u8> {
-        // If the payload already starts with 55 AA, assume it's a full packet
-        if payload.len() >= 2 && payload[0] == 0x55 && payload[1] == 0xAA {
+        // If the payload already starts with a known header, assume it's a full packet
+        if payload.len() >= 2 && [Protocol::Xiaomi, Protocol::Ninebot].iter().any(|p| payload[0..2] == p.header()) {
             return payload.to_vec();
         }
 
-        // Otherwise, wrap it in Xiaomi protocol
-        // 55 AA [Body] [Checksum]
-        let mut packet = vec![0x55, 0xAA];
-        packet.extend_from_slice(payload);
+        // Otherwise, wrap it in current protocol
+        // [Header] [Body] [Checksum]
+        let protocol = self.protocol();
+        let body = match protocol {
+            Protocol::Ninebot => Self::ninebot_body(payload),
+            _ => payload.to_vec(),
+        };
+        let mut packet = protocol.header().to_vec();
+        packet.extend_from_slice(&body);
 
-        let checksum = Self::calculate_checksum(payload);
+        let checksum = protocol.checksum(&body);
         packet.push((checksum & 0xFF) as u8);
         packet.push((checksum >> 8) as u8);
 
         packet
     }
-    
-    fn calculate_checksum(data: &[u8]) -> u16 {
-        let sum: u32 = data.iter().map(|&b| b as u32).sum();
-        ((sum ^ 0xFFFF) & 0xFFFF) as u16
+
+    /// Callers pass Xiaomi bodies: [len] [dst] [cmd] [attr] [payload], len counting cmd and attr.
+    /// Ninebot bodies carry the payload length and the sender: [len] [3E] [dst] [cmd] [attr] [payload]
+    fn ninebot_body(payload: &[u8]) -> Vec<u8> {
+        if payload.len() < 4 {
+            return payload.to_vec();
+        }
+
+        let data = &payload[4..];
+        let mut body = vec![data.len() as u8, Address::App.value(), payload[1], payload[2], payload[3]];
+        body.extend_from_slice(data);
+        body
     }
 }
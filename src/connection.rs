@@ -1,13 +1,18 @@
-use btleplug::platform::{Peripheral};
-use btleplug::api::{Peripheral as _};
+use btleplug::platform::{Adapter, Peripheral, PeripheralId};
+use btleplug::api::{Central, Peripheral as _};
 use anyhow::Result;
+use thiserror::Error;
+use uuid::Uuid;
 use tokio::time;
 use std::time::Duration;
+use std::sync::Mutex;
 
 // Windows BLE needs longer stabilization time after connection
 #[cfg(target_os = "windows")]
 const POST_CONNECT_DELAY_MS: u64 = 3000;
-#[cfg(not(target_os = "windows"))]
+#[cfg(target_os = "macos")]
+const POST_CONNECT_DELAY_MS: u64 = 1500;
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
 const POST_CONNECT_DELAY_MS: u64 = 1000;
 
 // Windows BLE needs longer delay between disconnect and reconnect
@@ -16,19 +21,111 @@ const RECONNECT_DELAY_SECS: u64 = 8;
 #[cfg(not(target_os = "windows"))]
 const RECONNECT_DELAY_SECS: u64 = 3;
 
+// CoreBluetooth reports characteristics only after service discovery completed
+#[cfg(target_os = "macos")]
+const SERVICE_DISCOVERY_RETRIES: u32 = 3;
+
+#[derive(Error, Debug)]
+pub enum ConnectionError {
+  #[error("Bluetooth adapter can scan, but can't connect to devices ({0}). Try other bluetooth adapter")]
+  AdapterCannotConnect(String),
+  #[error("Service {0} is missing after reconnect, even after discovering services again. Remove pairing of scooter in system settings and try again")]
+  MissingService(Uuid),
+  #[error("Bluetooth error: {0}")]
+  BluetoothError(btleplug::Error),
+}
+
+impl From<btleplug::Error> for ConnectionError {
+  fn from(other: btleplug::Error) -> Self {
+    ConnectionError::BluetoothError(other)
+  }
+}
+
+/**
+ * Messages of connect on adapters without GATT client (some USB dongles): BlueZ "org.bluez.Error.NotSupported",
+ * WinRT "The request is not supported"
+ */
+const CANNOT_CONNECT_MESSAGES : [&str; 2] = ["notsupported", "not supported"];
+
+/**
+ * Map error of connect, telling apart adapter which can't connect at all from failures worth retrying
+ */
+fn connect_error(err: btleplug::Error) -> ConnectionError {
+  match err {
+    btleplug::Error::NotSupported(reason) => ConnectionError::AdapterCannotConnect(reason),
+    err => {
+      let message = err.to_string().to_lowercase();
+      if CANNOT_CONNECT_MESSAGES.iter().any(|pattern| message.contains(pattern)) {
+        ConnectionError::AdapterCannotConnect(err.to_string())
+      } else {
+        ConnectionError::BluetoothError(err)
+      }
+    }
+  }
+}
+
 pub struct ConnectionHelper {
-  device: Peripheral
+  device: Mutex<Peripheral>,
+  adapter: Option<(Adapter, PeripheralId)>,
+  skip_discovery: bool,
+  required_service: Option<Uuid>
 }
 
 impl ConnectionHelper {
   pub fn new(device: &Peripheral) -> Self {
-    Self { device: device.clone() }
+    Self { device: Mutex::new(device.clone()), adapter: None, skip_discovery: false, required_service: None }
+  }
+
+  /// Same as `new`, but keeps the adapter so `reconnect` can fetch a fresh `Peripheral`
+  /// when the old handle stopped working after disconnect (seen on BlueZ and CoreBluetooth).
+  /// Use `device()` afterwards to get the handle which is currently connected.
+  pub fn with_adapter(device: &Peripheral, adapter: &Adapter) -> Self {
+    Self {
+      device: Mutex::new(device.clone()),
+      adapter: Some((adapter.clone(), device.id())),
+      skip_discovery: false,
+      required_service: None
+    }
+  }
+
+  /// Trust characteristics cached from earlier connection instead of discovering services after every connect,
+  /// saves seconds when reconnecting to bonded scooter. Services are still discovered when nothing is cached.
+  /// Only CoreBluetooth needs discovery on connect, on other platforms this changes nothing
+  pub fn with_skip_discovery(mut self, skip_discovery: bool) -> Self {
+    self.skip_discovery = skip_discovery;
+    self
+  }
+
+  /// Service which has to be reported after `reconnect`, none by default. Windows sometimes hands out GATT database
+  /// cached from previous connection without any characteristics, then services are discovered again and when that
+  /// does not help the device is disconnected and connected once more. Set MI service (FE95) before MiAuth login,
+  /// UART only devices (Ninebot 5A A5, NUS clones) don't have it
+  pub fn with_required_service(mut self, service: Option<Uuid>) -> Self {
+    self.required_service = service;
+    self
+  }
+
+  /// Peripheral managed by this helper, may be different from the one passed to constructor after `reconnect`
+  pub fn device(&self) -> Peripheral {
+    self.device.lock().unwrap().clone()
+  }
+
+  /// Replace peripheral with fresh one from adapter, returns false when helper has no adapter
+  async fn refresh_device(&self) -> Result<bool, btleplug::Error> {
+    let Some((adapter, id)) = &self.adapter else {
+      return Ok(false)
+    };
+
+    tracing::debug!("Fetching fresh peripheral from adapter");
+    let device = adapter.peripheral(id).await?;
+    *self.device.lock().unwrap() = device;
+    Ok(true)
   }
 
   /// Check if the device is actually connected and stable
   pub async fn is_stable_connected(&self) -> Result<bool, btleplug::Error> {
     // First check: is_connected()
-    if !self.device.is_connected().await? {
+    if !self.device().is_connected().await? {
       return Ok(false);
     }
     
@@ -36,7 +133,7 @@ impl ConnectionHelper {
     #[cfg(target_os = "windows")]
     {
       time::sleep(Duration::from_millis(100)).await;
-      if !self.device.is_connected().await? {
+      if !self.device().is_connected().await? {
         return Ok(false);
       }
     }
@@ -44,7 +141,9 @@ impl ConnectionHelper {
     Ok(true)
   }
 
-  pub async fn connect(&self) -> Result<bool, btleplug::Error> {
+  /// Connect unless already connected. Fails right away with `ConnectionError::AdapterCannotConnect`
+  /// when adapter reports that it does not support connecting, retrying would not help there
+  pub async fn connect(&self) -> Result<bool, ConnectionError> {
     tracing::debug!("Connecting to device.");
     let mut retries = 5;
     while retries >= 0 {
@@ -55,12 +154,14 @@ impl ConnectionHelper {
         // Verify still connected after delay
         if self.is_stable_connected().await? {
           tracing::debug!("Connection stable");
+          #[cfg(target_os = "macos")]
+          self.discover_services().await?;
           return Ok(true);
         } else {
           tracing::debug!("Connection dropped after stabilization delay");
         }
       }
-      match self.device.connect().await {
+      match self.device().connect().await {
         Ok(_) => {
           // Wait for connection to stabilize
           time::sleep(Duration::from_millis(POST_CONNECT_DELAY_MS)).await;
@@ -69,6 +170,8 @@ impl ConnectionHelper {
             // Additional stabilization for Windows
             #[cfg(target_os = "windows")]
             time::sleep(Duration::from_millis(1000)).await;
+            #[cfg(target_os = "macos")]
+            self.discover_services().await?;
             return Ok(true);
           } else {
             tracing::debug!("Connect call succeeded but device is not connected");
@@ -78,24 +181,51 @@ impl ConnectionHelper {
             }
           }
         },
-        Err(err) if retries > 0 => {
-          retries -= 1;
-          tracing::debug!("Retrying connection: {} retries left, reason: {}", retries, err);
-          time::sleep(Duration::from_secs(2)).await;
-        },
-
-        Err(err) => return Err(err)
+        Err(err) => match connect_error(err) {
+          ConnectionError::AdapterCannotConnect(reason) => {
+            tracing::error!("Adapter can't connect to devices: {}", reason);
+            return Err(ConnectionError::AdapterCannotConnect(reason))
+          },
+          err if retries > 0 => {
+            retries -= 1;
+            tracing::debug!("Retrying connection: {} retries left, reason: {}", retries, err);
+            time::sleep(Duration::from_secs(2)).await;
+          },
+          err => return Err(err)
+        }
       }
     }
 
     Ok(true)
   }
 
+  /// On macOS characteristics are not usable until discover_services finished,
+  /// using them earlier ends with "characteristic not found" errors
+  #[cfg(target_os = "macos")]
+  async fn discover_services(&self) -> Result<(), btleplug::Error> {
+    if self.skip_discovery && !self.device().characteristics().is_empty() {
+      tracing::debug!("Using cached characteristics, skipping discovery");
+      return Ok(());
+    }
+
+    for _ in 0..SERVICE_DISCOVERY_RETRIES {
+      self.device().discover_services().await?;
+      if !self.device().characteristics().is_empty() {
+        tracing::debug!("Services discovered");
+        return Ok(());
+      }
+      tracing::debug!("No characteristics after discovery, retrying...");
+      time::sleep(Duration::from_millis(500)).await;
+    }
+
+    Ok(())
+  }
+
   pub async fn disconnect(&self) -> Result<bool> {
     // Check multiple times on Windows due to connection state instability
     let mut actually_connected = false;
     for _ in 0..3 {
-      if self.device.is_connected().await? {
+      if self.device().is_connected().await? {
         actually_connected = true;
         break;
       }
@@ -107,7 +237,7 @@ impl ConnectionHelper {
       return Ok(true);
     }
 
-    if let Err(error) = self.device.disconnect().await {
+    if let Err(error) = self.device().disconnect().await {
       tracing::error!("Could not disconnect: {}", error);
       return Ok(false)
     }
@@ -118,7 +248,7 @@ impl ConnectionHelper {
       time::sleep(Duration::from_millis(500)).await;
       // Force wait until actually disconnected
       let mut wait_count = 0;
-      while self.device.is_connected().await.unwrap_or(false) && wait_count < 10 {
+      while self.device().is_connected().await.unwrap_or(false) && wait_count < 10 {
         time::sleep(Duration::from_millis(200)).await;
         wait_count += 1;
       }
@@ -128,6 +258,9 @@ impl ConnectionHelper {
     Ok(true)
   }
 
+  /// Disconnect and connect again. On macOS CoreBluetooth may hand out a new peripheral
+  /// after reconnecting, so the cached `Peripheral` can go stale. When created `with_adapter`
+  /// a failed connect is retried once with a fresh `Peripheral` fetched from the adapter.
   pub async fn reconnect(&self) -> Result<bool> {
     tracing::debug!("Reconnecting...");
     self.disconnect().await?;
@@ -136,7 +269,50 @@ impl ConnectionHelper {
     tracing::debug!("Waiting {}s before reconnecting (Windows BLE stabilization)...", RECONNECT_DELAY_SECS);
     time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
     
-    self.connect().await?;
+    if let Err(err) = self.connect().await {
+      tracing::debug!("Connect with old peripheral failed: {}", err);
+      if matches!(err, ConnectionError::AdapterCannotConnect(_)) || !self.refresh_device().await? {
+        return Err(err.into())
+      }
+      self.connect().await?;
+    }
+
+    self.ensure_required_service().await?;
     Ok(true)
   }
+
+  fn has_required_service(&self) -> bool {
+    let Some(service) = self.required_service else {
+      return true
+    };
+    self.device().characteristics().iter().any(|characteristic| characteristic.service_uuid == service)
+  }
+
+  /// Characteristics cached by OS can be stale after reconnect: discover services again, then try
+  /// full disconnect / connect cycle before giving up with `ConnectionError::MissingService`
+  pub(crate) async fn ensure_required_service(&self) -> Result<()> {
+    let Some(service) = self.required_service else {
+      return Ok(())
+    };
+    if self.has_required_service() {
+      return Ok(())
+    }
+
+    tracing::debug!("Service {} missing after connect, discovering services again", service);
+    self.device().discover_services().await?;
+    if self.has_required_service() {
+      return Ok(())
+    }
+
+    tracing::warn!("Service {} still missing, cached GATT database is probably stale. Reconnecting", service);
+    self.disconnect().await?;
+    time::sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+    self.connect().await?;
+    self.device().discover_services().await?;
+    if self.has_required_service() {
+      return Ok(())
+    }
+
+    Err(ConnectionError::MissingService(service).into())
+  }
 }
@@ -32,6 +32,7 @@ impl Registers {
 }
 
 #[allow(non_camel_case_types)]
+#[derive(Clone)]
 pub enum MiCommands {
   CMD_GET_INFO,
   CMD_SET_KEY,
@@ -91,16 +92,25 @@ impl Debug for MiCommands {
   }
 }
 
+impl MiCommands {
+  /**
+   * Match raw notification against responses scooter sends during MiAuth handshake
+   */
+  pub fn from_response(bytes: &[u8]) -> Option<Self> {
+    if bytes == Self::RCV_RDY.to_bytes() { return Some(Self::RCV_RDY) }
+    if bytes == Self::CMD_SEND_DATA.to_bytes() { return Some(Self::CMD_SEND_DATA) }
+    if bytes == Self::RCV_OK.to_bytes() { return Some(Self::RCV_OK) }
+    if bytes == Self::RCV_AUTH_OK.to_bytes() { return Some(Self::RCV_AUTH_OK) }
+    if bytes == Self::RCV_AUTH_ERR.to_bytes() { return Some(Self::RCV_AUTH_ERR) }
+    if bytes == Self::RCV_LOGIN_OK.to_bytes() { return Some(Self::RCV_LOGIN_OK) }
+    if bytes == Self::RCV_LOGIN_ERR.to_bytes() { return Some(Self::RCV_LOGIN_ERR) }
+    None
+  }
+}
+
 impl TryFrom<ValueNotification> for MiCommands {
   type Error = &'static str;
   fn try_from(data: ValueNotification) -> std::result::Result<Self, <Self as std::convert::TryFrom<ValueNotification>>::Error> {
-    if data.value == Self::RCV_RDY.to_bytes() { return Ok(Self::RCV_RDY) }
-    if data.value == Self::CMD_SEND_DATA.to_bytes() { return Ok(Self::CMD_SEND_DATA) }
-    if data.value == Self::RCV_OK.to_bytes() { return Ok(Self::RCV_OK) }
-    if data.value == Self::RCV_AUTH_OK.to_bytes() { return Ok(Self::RCV_AUTH_OK) }
-    if data.value == Self::RCV_AUTH_ERR.to_bytes() { return Ok(Self::RCV_AUTH_ERR) }
-    if data.value == Self::RCV_LOGIN_OK.to_bytes() { return Ok(Self::RCV_LOGIN_OK) }
-    if data.value == Self::RCV_LOGIN_ERR.to_bytes() { return Ok(Self::RCV_LOGIN_ERR) }
-    Err("This is not response")
+    Self::from_response(&data.value).ok_or("This is not response")
   }
 }
\ No newline at end of file
@@ -0,0 +1,163 @@
+use crate::connection::ConnectionHelper;
+use crate::consts::Registers;
+use crate::login::LoginRequest;
+use crate::mi_crypto::AuthToken;
+use crate::scanner::ScooterScanner;
+use crate::session::{MiSession, MotorInfo, BatteryInfo, TailLight};
+
+use crate::error::{Error, Result};
+
+use anyhow::anyhow;
+use btleplug::api::BDAddr;
+pub use tokio_util::sync::CancellationToken;
+
+/**
+ * Stages of ScooterController::connect_with_progress, in order they are reported
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressEvent {
+  Scanning,
+  Connecting,
+  DiscoveringServices,
+  Authenticating,
+  Ready,
+}
+
+impl std::fmt::Display for ProgressEvent {
+  fn fmt(&self, form: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let stage = match self {
+      ProgressEvent::Scanning             => "Scanning for device...",
+      ProgressEvent::Connecting           => "Connecting...",
+      ProgressEvent::DiscoveringServices  => "Discovering services...",
+      ProgressEvent::Authenticating       => "Authenticating...",
+      ProgressEvent::Ready                => "Ready",
+    };
+    form.write_str(stage)
+  }
+}
+
+/**
+ * Run session command, when it fails with link error (see Error::is_link_error) resume session and retry it once.
+ * Other errors are returned right away
+ */
+macro_rules! with_reconnect {
+  ($controller:ident, $command:ident ( $($arg:expr),* )) => {{
+    match $controller.session.$command($($arg),*).await.map_err(Error::from) {
+      Ok(value) => Ok(value),
+      Err(err) if err.is_link_error() => {
+        tracing::debug!("{} failed: {}, reconnecting", stringify!($command), err);
+        $controller.reconnect().await?;
+        Ok($controller.session.$command($($arg),*).await?)
+      },
+      Err(err) => Err(err)
+    }
+  }};
+}
+
+/**
+ * Scanner, connection and session in one place. Commands which fail because connection was lost or scooter
+ * did not answer are retried once after session resumed, other errors are returned as they are.
+ * Use MiSession directly when you need full control.
+ * Errors of every stage are returned as crate::Error, match on its variants to tell them apart.
+ *
+ * ```no_run
+ * # async fn run(mac: btleplug::api::BDAddr, token: ninebot_ble::AuthToken) -> anyhow::Result<()> {
+ * use ninebot_ble::ScooterController;
+ *
+ * let mut scooter = ScooterController::connect(&mac, &token).await?;
+ * println!("Battery: {}%", scooter.battery().await?.percent);
+ * # Ok(())
+ * # }
+ * ```
+ */
+pub struct ScooterController {
+  connection: ConnectionHelper,
+  session: MiSession,
+}
+
+impl ScooterController {
+  /**
+   * Find scooter with address, connect and login with token from registration
+   */
+  pub async fn connect(addr: &BDAddr, token: &AuthToken) -> Result<Self> {
+    Self::connect_with_progress(addr, token, |_| {}).await
+  }
+
+  /**
+   * Same as connect, on_progress is called when every stage starts, e.g. to drive progress bar in UI
+   */
+  pub async fn connect_with_progress<F: FnMut(ProgressEvent)>(addr: &BDAddr, token: &AuthToken, mut on_progress: F) -> Result<Self> {
+    on_progress(ProgressEvent::Scanning);
+    let scanner = ScooterScanner::new().await?;
+    let device = scanner.connect_known(addr).await?;
+
+    on_progress(ProgressEvent::Connecting);
+    let connection = ConnectionHelper::with_adapter(&device, &scanner.central)
+      .with_required_service(Some(Registers::AUTH.to_uuid()));
+    connection.connect().await?;
+
+    on_progress(ProgressEvent::DiscoveringServices);
+    connection.ensure_required_service().await?;
+    let mut login = LoginRequest::new(&connection.device(), token).await?;
+
+    on_progress(ProgressEvent::Authenticating);
+    let session = login.start().await?;
+
+    on_progress(ProgressEvent::Ready);
+    Ok(Self { connection, session })
+  }
+
+  /**
+   * Same as connect, but gives up as soon as cancel is cancelled from other task. Scanning is stopped and
+   * scooter disconnected, so adapter can be used right away again
+   */
+  pub async fn connect_cancellable(addr: &BDAddr, token: &AuthToken, cancel: &CancellationToken) -> Result<Self> {
+    tokio::select! {
+      controller = Self::connect(addr, token) => controller,
+      _ = cancel.cancelled() => {
+        tracing::debug!("Connect to {} cancelled", addr);
+        crate::scanner::release_connect(addr).await;
+        Err(anyhow!("Connect to {} cancelled", addr).into())
+      }
+    }
+  }
+
+  /**
+   * Reconnect and login again with MiSession::resume, session settings (verify_writes, retries of first command,
+   * write type, pacing) are kept
+   */
+  pub async fn reconnect(&mut self) -> Result<()> {
+    tracing::debug!("Reconnecting controller");
+    Ok(self.session.resume().await?)
+  }
+
+  /**
+   * Underlying session for commands not covered by controller
+   */
+  pub fn session(&mut self) -> &mut MiSession {
+    &mut self.session
+  }
+
+  pub async fn status(&mut self) -> Result<MotorInfo> {
+    with_reconnect!(self, motor_info())
+  }
+
+  pub async fn battery(&mut self) -> Result<BatteryInfo> {
+    with_reconnect!(self, battery_info())
+  }
+
+  pub async fn set_cruise(&mut self, on: bool) -> Result<()> {
+    with_reconnect!(self, set_cruise(on))
+  }
+
+  pub async fn set_tail_light(&mut self, mode: TailLight) -> Result<()> {
+    with_reconnect!(self, set_tail_light(mode))
+  }
+
+  pub async fn disconnect(self) -> Result<bool> {
+    if let Err(err) = self.session.close().await {
+      tracing::debug!("Could not close session: {}", err);
+    }
+    Ok(self.connection.disconnect().await?)
+  }
+}
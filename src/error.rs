@@ -0,0 +1,95 @@
+/*!
+ * One error type for the high-level API. Modules keep their own errors, this wraps them so callers can use ?
+ * across scanner, connection, login and session without juggling anyhow and typed errors
+ */
+use crate::connection::ConnectionError;
+use crate::login::LoginError;
+use crate::mi_crypto::{MiAuthError, MiCryptoError};
+use crate::ninebot::NinebotError;
+use crate::register::RegistrationError;
+use crate::scanner::ScannerError;
+use crate::session::SessionError;
+use crate::util::Timeout;
+
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+  #[error(transparent)]
+  Scanner(#[from] ScannerError),
+  #[error(transparent)]
+  Connection(#[from] ConnectionError),
+  #[error(transparent)]
+  Registration(#[from] RegistrationError),
+  #[error(transparent)]
+  Login(#[from] LoginError),
+  #[error(transparent)]
+  Session(#[from] SessionError),
+  #[error(transparent)]
+  Crypto(#[from] MiCryptoError),
+  #[error(transparent)]
+  Auth(#[from] MiAuthError),
+  #[error(transparent)]
+  Ninebot(#[from] NinebotError),
+  #[error(transparent)]
+  Timeout(#[from] Timeout),
+  #[error(transparent)]
+  Bluetooth(#[from] btleplug::Error),
+  /**
+   * Anything not covered by typed variants, e.g. malformed response. Whole anyhow context chain is kept,
+   * Display shows outermost context and source() walks the rest
+   */
+  #[error(transparent)]
+  Other(anyhow::Error),
+}
+
+impl Error {
+  /**
+   * Error caused by lost link or scooter not answering, worth retrying after session resumed.
+   * Errors reported by scooter or malformed responses would fail again the same way
+   */
+  pub fn is_link_error(&self) -> bool {
+    matches!(self,
+      Error::Timeout(_) | Error::Bluetooth(_) | Error::Connection(_) | Error::Session(SessionError::NotAuthenticated)
+    )
+  }
+}
+
+/**
+ * Try every typed variant in order, first one found in error wins
+ */
+macro_rules! downcast {
+  ($err:ident, $($variant:ident => $error:ty),*) => {{
+    $(
+      let $err = match $err.downcast::<$error>() {
+        Ok(typed) => return Error::$variant(typed),
+        Err(err) => err,
+      };
+    )*
+    Error::Other($err)
+  }};
+}
+
+/**
+ * Most of session API returns anyhow errors, typed error inside them becomes its own variant.
+ * When typed error was wrapped with anyhow context, only typed error is kept and context is lost,
+ * format anyhow error with {:#} before converting when context is needed in logs
+ */
+impl From<anyhow::Error> for Error {
+  fn from(err: anyhow::Error) -> Self {
+    downcast!(err,
+      Scanner => ScannerError,
+      Connection => ConnectionError,
+      Registration => RegistrationError,
+      Login => LoginError,
+      Session => SessionError,
+      Crypto => MiCryptoError,
+      Auth => MiAuthError,
+      Ninebot => NinebotError,
+      Timeout => Timeout,
+      Bluetooth => btleplug::Error
+    )
+  }
+}
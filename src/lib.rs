@@ -1,63 +1,102 @@
+// Without ble feature only protocol_core is left, which needs just core and alloc. Hosted targets keep std
+// because of cdylib crate type, bare metal firmware (target_os = "none") builds as no_std
+#![cfg_attr(all(not(feature = "ble"), target_os = "none"), no_std)]
+
+extern crate alloc;
+#[cfg(feature = "ble")]
 extern crate uuid;
 
 // 宣告模組
+pub mod protocol_core;
+#[cfg(feature = "ble")]
 pub mod mi_crypto;
+#[cfg(feature = "ble")]
 pub mod protocol;
+#[cfg(feature = "ble")]
 pub mod consts;
+#[cfg(feature = "ble")]
 pub mod clone_connection;
+#[cfg(feature = "ble")]
 pub mod login;
+#[cfg(feature = "ble")]
 pub mod scanner;
+#[cfg(feature = "ble")]
 pub mod session;
+#[cfg(feature = "ble")]
+pub mod ninebot;
+#[cfg(feature = "ble")]
 pub mod android_api;
+#[cfg(feature = "ble")]
 pub mod register;
+#[cfg(feature = "ble")]
 pub mod connection;
+#[cfg(feature = "ble")]
+pub mod advertisement;
+#[cfg(feature = "ble")]
+pub mod controller;
+#[cfg(feature = "ble")]
+pub mod util;
+#[cfg(feature = "ble")]
+pub mod error;
 
 // 引用
+#[cfg(feature = "ble")]
 pub use clone_connection::ScooterConnection;
-pub use scanner::{ScooterScanner, ScannerEvent};
-
-pub use mi_crypto::AuthToken;
+#[cfg(feature = "ble")]
+pub use scanner::{ScooterScanner, ScooterScannerBuilder, ScannerEvent, parse_mac};
+#[cfg(feature = "ble")]
+pub use advertisement::PassiveTelemetry;
+
+#[cfg(feature = "ble")]
+pub use mi_crypto::{AuthToken, TokenStore};
+#[cfg(feature = "ble")]
 pub use register::{RegistrationRequest, RegistrationError};
+#[cfg(feature = "ble")]
 pub use login::LoginRequest;
-pub use connection::ConnectionHelper;
-
-#[cfg(target_os = "android")]
+#[cfg(feature = "ble")]
+pub use connection::{ConnectionHelper, ConnectionError};
+#[cfg(feature = "ble")]
+pub use controller::{ScooterController, ProgressEvent, CancellationToken};
+#[cfg(feature = "ble")]
+pub use error::{Error, Result};
+
+#[cfg(all(target_os = "android", feature = "ble"))]
 use jni::JNIEnv;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use jni::objects::{JClass, JObject, JString, JValue, GlobalRef};
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use jni::JavaVM;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
+use jni::sys::jbyteArray;
+#[cfg(all(target_os = "android", feature = "ble"))]
 use log::{info, error, LevelFilter};
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use android_logger::Config;
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use tokio::runtime::Runtime;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use tokio::sync::mpsc;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use btleplug::platform::Manager;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use std::time::Duration;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use std::sync::{Mutex, Arc};
-#[cfg(target_os = "android")]
-use std::str::FromStr;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 use once_cell::sync::Lazy;
 
 // --- Globals & Types (Thread-Safe + Arc) ---
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 static JAVA_VM: Lazy<Mutex<Option<Arc<JavaVM>>>> = Lazy::new(|| Mutex::new(None));
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 static BLE_MANAGER_CLASS: Lazy<Mutex<Option<GlobalRef>>> = Lazy::new(|| Mutex::new(None));
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[derive(Debug)]
 pub enum BleEvent {
     Status(String),
@@ -65,11 +104,14 @@ pub enum BleEvent {
     Data { speed: f64, battery: i32, temp: f64 },
 }
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
+static CONNECT_CANCEL: Lazy<Mutex<Option<CancellationToken>>> = Lazy::new(|| Mutex::new(None));
+
+#[cfg(all(target_os = "android", feature = "ble"))]
 static EVENT_TX: Lazy<Mutex<Option<mpsc::Sender<BleEvent>>>> = Lazy::new(|| Mutex::new(None));
 
 // --- 1. Init ---
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[no_mangle]
 pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeInit(
     env: JNIEnv,
@@ -155,7 +197,7 @@ pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeInit(
 }
 
 // --- 2. Start Scan ---
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[no_mangle]
 pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeStartScan(
     _env: JNIEnv,
@@ -231,7 +273,8 @@ pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeStartScan(
                     let name = props.local_name.unwrap_or("Unknown".to_string());
 
                     if !name.is_empty() {
-                        if let Some(tx) = EVENT_TX.lock().unwrap().clone() {
+                        let tx = EVENT_TX.lock().unwrap().clone();
+                        if let Some(tx) = tx {
                             let _ = tx.send(BleEvent::DeviceFound { name: name.clone(), address: addr.clone() }).await;
                         }
                     }
@@ -242,7 +285,7 @@ pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeStartScan(
 }
 
 // --- 3. Connect & Monitor ---
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[no_mangle]
 pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeConnect(
     env: JNIEnv,
@@ -254,166 +297,251 @@ pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeConnect(
         Err(_) => return,
     };
 
-    info!("Rust: Connecting to device: {}", address);
+    // Without token only clones which skip authentication can be used
+    let token: AuthToken = [0u8; 12];
+    connect_and_monitor(address, token);
+}
 
-    std::thread::spawn(move || {
-        let rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            // Retrieve VM safely
-            let vm_arc = {
-                let guard = JAVA_VM.lock().unwrap();
-                guard.as_ref().cloned()
-            };
+/// Connect using token saved during registration (`.mi-token` contents, raw or hex encoded)
+#[cfg(all(target_os = "android", feature = "ble"))]
+#[no_mangle]
+pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeConnectWithToken(
+    env: JNIEnv,
+    _this: JObject,
+    j_address: JString,
+    j_token: jbyteArray,
+) {
+    let address: String = match env.get_string(j_address) {
+        Ok(s) => s.into(),
+        Err(_) => return,
+    };
 
-            let vm = match vm_arc {
-                Some(v) => v,
-                None => {
-                    error!("JAVA_VM not initialized");
-                    return;
-                }
-            };
+    let bytes = match env.convert_byte_array(j_token) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Cannot get token: {:?}", e);
+            return;
+        }
+    };
 
-            let _guard = match vm.attach_current_thread_permanently() {
-                Ok(g) => g,
-                Err(e) => {
-                    error!("Connect thread attach failed: {:?}", e);
-                    return;
-                }
-            };
+    let token = match mi_crypto::import_token(&bytes) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Invalid token: {}", e);
+            return;
+        }
+    };
 
-            send_status("Initializing scanner...").await;
+    connect_and_monitor(address, token);
+}
 
-            // Create scanner and find device
-            let mut scanner = match ScooterScanner::new().await {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("Failed to create scanner: {:?}", e);
-                    send_status(&format!("Scanner Error: {}", e)).await;
-                    return;
-                }
-            };
+#[cfg(all(target_os = "android", feature = "ble"))]
+fn connect_and_monitor(address: String, token: AuthToken) {
+    info!("Rust: Connecting to device: {}", address);
 
-            let bd_addr = match btleplug::api::BDAddr::from_str(&address) {
-                Ok(addr) => addr,
-                Err(e) => {
-                    error!("Invalid MAC address: {:?}", e);
-                    send_status("Invalid MAC Address").await;
-                    return;
-                }
-            };
+    // Only one connect runs at a time, starting new one cancels previous
+    let cancel = CancellationToken::new();
+    if let Some(previous) = CONNECT_CANCEL.lock().unwrap().replace(cancel.clone()) {
+        previous.cancel();
+    }
 
-            send_status("Scanning for device...").await;
+    std::thread::spawn(move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let flow = async {
+                // Retrieve VM safely
+                let vm_arc = {
+                    let guard = JAVA_VM.lock().unwrap();
+                    guard.as_ref().cloned()
+                };
+
+                let vm = match vm_arc {
+                    Some(v) => v,
+                    None => {
+                        error!("JAVA_VM not initialized");
+                        return;
+                    }
+                };
 
-            let tracked_device = match scanner.wait_for(&bd_addr).await {
-                Ok(device) => device,
-                Err(e) => {
-                    error!("Device not found: {:?}", e);
-                    send_status("Device Not Found").await;
-                    return;
-                }
-            };
+                let _guard = match vm.attach_current_thread_permanently() {
+                    Ok(g) => g,
+                    Err(e) => {
+                        error!("Connect thread attach failed: {:?}", e);
+                        return;
+                    }
+                };
 
-            send_status("Device found. Connecting...").await;
+                send_status("Initializing scanner...").await;
 
-            let peripheral = match scanner.peripheral(&tracked_device).await {
-                Ok(p) => p,
-                Err(e) => {
-                    error!("Failed to get peripheral: {:?}", e);
-                    send_status("Peripheral Error").await;
-                    return;
-                }
-            };
+                // Create scanner and find device
+                let mut scanner = match ScooterScanner::new().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("Failed to create scanner: {:?}", e);
+                        send_status(&format!("Scanner Error: {}", e)).await;
+                        return;
+                    }
+                };
+
+                let bd_addr = match parse_mac(&address) {
+                    Ok(addr) => addr,
+                    Err(e) => {
+                        error!("Invalid MAC address: {:?}", e);
+                        send_status("Invalid MAC Address").await;
+                        return;
+                    }
+                };
 
-            // Connect to device
-            if let Err(e) = peripheral.connect().await {
-                error!("Connection failed: {:?}", e);
-                send_status(&format!("Connection Failed: {}", e)).await;
-                return;
-            }
+                send_status("Scanning for device...").await;
+
+                let tracked_device = match scanner.wait_for(&bd_addr).await {
+                    Ok(device) => device,
+                    Err(e @ scanner::ScannerError::AdapterUnavailable { .. }) => {
+                        error!("Bluetooth unavailable: {:?}", e);
+                        send_status(&e.to_string()).await;
+                        return;
+                    }
+                    Err(e) => {
+                        error!("Device not found: {:?}", e);
+                        send_status("Device Not Found").await;
+                        return;
+                    }
+                };
 
-            send_status("Connected. Authenticating...").await;
+                send_status("Device found. Connecting...").await;
 
-            // Use dummy token for demo (in real app, use proper authentication)
-            let token: AuthToken = [0u8; 12];
+                let peripheral = match scanner.peripheral(&tracked_device).await {
+                    Ok(p) => p,
+                    Err(e) => {
+                        error!("Failed to get peripheral: {:?}", e);
+                        send_status("Peripheral Error").await;
+                        return;
+                    }
+                };
 
-            let mut login_req = match LoginRequest::new(&peripheral, &token).await {
-                Ok(req) => req,
-                Err(e) => {
-                    error!("Login request failed: {:?}", e);
-                    send_status(&format!("Login Init Failed: {}", e)).await;
+                // Connect to device
+                if let Err(e) = peripheral.connect().await {
+                    error!("Connection failed: {:?}", e);
+                    send_status(&format!("Connection Failed: {}", e)).await;
                     return;
                 }
-            };
 
-            let session = match login_req.start().await {
-                Ok(sess) => sess,
-                Err(e) => {
-                    error!("Authentication failed: {:?}", e);
-                    send_status(&format!("Auth Failed: {}", e)).await;
-                    return;
+                send_status("Connected. Authenticating...").await;
+
+                let old_session = crate::android_api::SESSION.lock().await.take();
+                if let Some(old_session) = old_session {
+                    if let Err(e) = old_session.close().await {
+                        error!("Could not close previous session: {}", e);
+                    }
                 }
-            };
 
-            send_status("Authenticated. Starting monitoring...").await;
+                let session = match crate::android_api::login_with_retry(&peripheral, &token).await {
+                    Ok(sess) => sess,
+                    Err(e) => {
+                        error!("Authentication failed: {}", e);
+                        send_status(&e).await;
+                        return;
+                    }
+                };
 
-            // Store session globally for JNI calls
-            {
-                *crate::android_api::SESSION.lock().unwrap() = Some(session);
-            }
+                send_status("Authenticated. Starting monitoring...").await;
 
-            send_status("Ready").await;
+                let disconnected = session.on_disconnect();
+                tokio::pin!(disconnected);
 
-            // Start real-time monitoring loop
-            let mut ticker = tokio::time::interval(Duration::from_millis(1000));
+                // Store session globally for JNI calls
+                {
+                    *crate::android_api::SESSION.lock().await = Some(session);
+                }
 
-            loop {
-                tokio::select! {
-                    _ = ticker.tick() => {
-                        // Query real-time data
-                        if let Some(tx) = EVENT_TX.lock().unwrap().clone() {
-                            if let Some(ref mut session) = *crate::android_api::SESSION.lock().unwrap() {
-                                let result: Result<crate::session::MotorInfo, anyhow::Error> = session.motor_info().await;
-                                match result {
-                                    Ok(info) => {
-                                        let _ = tx.send(BleEvent::Data {
-                                            speed: info.speed_kmh as f64,
-                                            battery: info.battery_percent as i32,
-                                            temp: info.frame_temperature as f64
-                                        }).await;
-                                    },
-                                    Err(e) => {
-                                        error!("Failed to get motor info: {:?}", e);
+                send_status("Ready").await;
+
+                // Start real-time monitoring loop
+                let mut ticker = tokio::time::interval(Duration::from_millis(1000));
+
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            // Query real-time data
+                            let tx = EVENT_TX.lock().unwrap().clone();
+                            if let Some(tx) = tx {
+                                if let Some(ref mut session) = *crate::android_api::SESSION.lock().await {
+                                    let result: Result<crate::session::MotorInfo, anyhow::Error> = session.motor_info().await;
+                                    match result {
+                                        Ok(info) => {
+                                            let _ = tx.send(BleEvent::Data {
+                                                speed: info.speed_kmh as f64,
+                                                battery: info.battery_percent as i32,
+                                                temp: info.frame_temperature as f64
+                                            }).await;
+                                        },
+                                        Err(e) => {
+                                            error!("Failed to get motor info: {:?}", e);
+                                        }
                                     }
                                 }
                             }
                         }
+                        _ = &mut disconnected => {
+                            info!("Scooter disconnected");
+                            send_status("Disconnected").await;
+                            break;
+                        }
                     }
                 }
+            };
+
+            tokio::select! {
+                _ = flow => {}
+                _ = cancel.cancelled() => {
+                    info!("Connect cancelled");
+                    let old_session = crate::android_api::SESSION.lock().await.take();
+                    if let Some(old_session) = old_session {
+                        if let Err(e) = old_session.close().await {
+                            error!("Could not close cancelled session: {}", e);
+                        }
+                    }
+                    if let Ok(addr) = parse_mac(&address) {
+                        crate::scanner::release_connect(&addr).await;
+                    }
+                    send_status("Cancelled").await;
+                }
             }
         });
     });
 }
 
+/// Stop connect started with nativeConnect, also stops monitoring of connected scooter
+#[cfg(all(target_os = "android", feature = "ble"))]
+#[no_mangle]
+pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeCancelConnect(_env: JNIEnv, _this: JObject) {
+    if let Some(cancel) = CONNECT_CANCEL.lock().unwrap().take() {
+        cancel.cancel();
+    }
+}
+
 // 輔助函數
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 async fn send_status(msg: &str) {
-    if let Some(tx) = EVENT_TX.lock().unwrap().clone() {
+    let tx = EVENT_TX.lock().unwrap().clone();
+    if let Some(tx) = tx {
         let _ = tx.send(BleEvent::Status(msg.to_string())).await;
     }
 }
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 async fn send_data(speed: f64, battery: i32, temp: f64) {
-    if let Some(tx) = EVENT_TX.lock().unwrap().clone() {
+    let tx = EVENT_TX.lock().unwrap().clone();
+    if let Some(tx) = tx {
         let _ = tx.send(BleEvent::Data { speed, battery, temp }).await;
     }
 }
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[no_mangle]
 pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeStopScan(_env: JNIEnv, _this: JObject) {}
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "ble"))]
 #[no_mangle]
 pub extern "C" fn Java_com_rokid_m365hud_BleManager_nativeStartMonitoring(env: JNIEnv, this: JObject, j_address: JString) {
     Java_com_rokid_m365hud_BleManager_nativeConnect(env, this, j_address);
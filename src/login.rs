@@ -1,13 +1,10 @@
-use crate::mi_crypto::{
-  AuthToken, RandKey, LoginKeychain,
-  gen_rand_key, calc_login_did
-};
+use crate::mi_crypto::{AuthToken, LoginKeychain, MiAuthStateMachine, MiAuthError};
 use crate::session::MiSession;
-use crate::consts::{MiCommands, Registers};
 use crate::protocol::MiProtocol;
+use crate::connection::ConnectionHelper;
 use anyhow::Result;
-use pretty_hex::*;
 use btleplug::platform::Peripheral;
+use btleplug::api::Peripheral as _;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -34,117 +31,79 @@ impl From<anyhow::Error> for LoginError {
  */
 pub struct LoginRequest {
   protocol: MiProtocol,
-  auth_token: AuthToken,
-  rand_key: RandKey,
+  machine: MiAuthStateMachine,
   device: Peripheral,
-  remote_info: Option<[u8; 32]>,
-  keys: Option<LoginKeychain>,
-  remote_key: Option<Vec<u8>>,
+  auth_token: AuthToken,
 }
 
 impl LoginRequest {
   pub async fn new(device : &Peripheral, token: &AuthToken) -> Result<Self> {
     let protocol = MiProtocol::new(device).await?;
-    let rand_key = gen_rand_key();
+    let machine = MiAuthStateMachine::login(token);
 
     Ok(
       Self {
-        remote_info: None,
-        remote_key: None,
-        keys: None,
-        rand_key,
         protocol,
+        machine,
         device: device.clone(),
-        auth_token: token.clone()
+        auth_token: *token,
       }
     )
   }
 
+  #[tracing::instrument(skip(self), fields(addr = %self.device.address()))]
   pub async fn start(&mut self) -> Result<MiSession> {
-    self.send_key().await?;
-    self.read_remote_key().await?;
-    self.read_remote_info().await?;
-    self.validate_remote_key_and_send_did().await?;
-    self.confirm().await?;
-
-    self.protocol.dispose().await?;
-    let keys = self.keys.as_ref().unwrap();
-    let session = MiSession::new(&self.device, keys).await?;
+    let keys = self.login_keys().await?;
+    let session = MiSession::new(&self.device, &keys).await?.with_token(&self.auth_token);
     Ok(session)
   }
 
-  async fn send_key(&mut self) -> Result<bool> {
-    self.protocol.write(&Registers::UPNP, MiCommands::CMD_LOGIN).await?;
-    self.protocol.write(&Registers::AVDTP, MiCommands::CMD_SEND_KEY).await?;
-
-    self.protocol.wait_for_scooter_to_receive_data().await?;
-    self.protocol.write_mi_parcel(&Registers::AVDTP, &self.rand_key).await?;
-    self.protocol.wait_for_scooter_to_ack_data().await?;
-
-    Ok(true)
-  }
+  /**
+   * Login without creating session, e.g. to give new keys to session which keeps its own UART setup
+   */
+  pub(crate) async fn login_keys(&mut self) -> Result<LoginKeychain> {
+    self.authenticate().await?;
 
-  async fn read_remote_key(&mut self) -> Result<bool> {
-    tracing::debug!("<- remote_key");
-    let remote_key = self.protocol.read_mi_parcel(&Registers::AVDTP).await?;
-    self.remote_key = Some(remote_key);
-
-    Ok(true)
-  }
-
-  async fn read_remote_info(&mut self) -> Result<bool> {
-    tracing::debug!("<- remote_info");
-    let remote_info = self.protocol.read_mi_parcel(&Registers::AVDTP).await?;
-    self.remote_info = Some(remote_info.try_into().unwrap());
-
-    Ok(true)
+    self.protocol.dispose().await?;
+    let keys = self.machine.keys().ok_or(LoginError::LoginFailed)?;
+    Ok(keys.clone())
   }
 
-  async fn validate_remote_key_and_send_did(&mut self) -> Result<bool, LoginError> {
-    tracing::info!("Validating did");
-
-    let rand_key = self.rand_key.as_mut();
-    let remote_key = self.remote_key.as_mut().unwrap();
-    let remote_info = self.remote_info.unwrap();
-
-    let (info, expected_remote_info, keys) = calc_login_did(rand_key, remote_key, &self.auth_token);
-    if remote_info == expected_remote_info {
-      tracing::debug!("Remote info is as expected, sending did");
-
-      self.protocol.write(&Registers::AVDTP, MiCommands::CMD_SEND_INFO).await?;
-
-      self.protocol.wait_for_scooter_to_receive_data().await?;
-      self.protocol.write_mi_parcel(&Registers::AVDTP, &info).await?;
-      self.protocol.wait_for_scooter_to_ack_data().await?;
-      self.keys = Some(keys);
-
-      return Ok(true)
+  /**
+   * Check if token is still accepted by scooter, e.g. it was not invalidated by pairing with other app.
+   * Login handshake is performed without creating session and device is disconnected afterwards.
+   * Rejected token returns false, error is returned only when communication with scooter failed
+   */
+  pub async fn verify(device: &Peripheral, token: &AuthToken) -> Result<bool> {
+    let mut request = Self::new(device, token).await?;
+    let result = request.authenticate().await;
+
+    if let Err(err) = request.protocol.dispose().await {
+      tracing::warn!("Could not dispose login protocol: {}", err);
     }
+    ConnectionHelper::new(device).disconnect().await?;
 
-    tracing::error!("Scooter send invalid remote key:");
-    tracing::error!("   Expected: {:?}", expected_remote_info.hex_dump());
-    tracing::error!("   Received: {:?}", remote_info.hex_dump());
-
-    Err(LoginError::InvalidDid)
-  }
-
-  async fn confirm(&mut self) -> Result<bool, LoginError> {
-    match self.protocol.next_mi_response().await {
-      Some(MiCommands::RCV_LOGIN_OK) => {
-        tracing::info!("Logged in!");
-      },
-
-      Some(error) => {
-        tracing::error!("Login failed: {:?}", error);
-        return Err(LoginError::LoginFailed)
+    match result {
+      Ok(()) => Ok(true),
+      Err(LoginError::InvalidDid) | Err(LoginError::LoginFailed) => {
+        tracing::info!("Scooter rejected token");
+        Ok(false)
       },
+      Err(LoginError::Other(err)) => Err(err)
+    }
+  }
 
-      None => {
-        tracing::error!("Login failed, scooter did not respond");
-        return Err(LoginError::LoginFailed)
+  async fn authenticate(&mut self) -> Result<(), LoginError> {
+    match self.protocol.run_handshake(&mut self.machine).await {
+      Ok(()) => Ok(()),
+      Err(err) => match err.downcast_ref::<MiAuthError>() {
+        Some(MiAuthError::InvalidDid) => Err(LoginError::InvalidDid),
+        Some(MiAuthError::LoginFailed) => {
+          tracing::error!("Login failed: {}", err);
+          Err(LoginError::LoginFailed)
+        },
+        _ => Err(LoginError::Other(err))
       }
     }
-
-    Ok(true)
   }
 }
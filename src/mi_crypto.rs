@@ -1,15 +1,20 @@
 use pretty_hex::*;
 use ccm::{Ccm, consts::{U4, U12}};
-use ccm::aead::{Aead, NewAead, generic_array::GenericArray};
+use ccm::aead::{Aead, AeadInPlace, NewAead, generic_array::GenericArray};
 use aes::Aes128;
 use ccm::aead::Payload;
 use sha2::Sha256;
 use hkdf::Hkdf;
 use hmac::{Hmac, Mac};
-use p256::{PublicKey, ecdh::EphemeralSecret};
+use p256::{PublicKey, EncodedPoint, ecdh::EphemeralSecret};
+use crate::consts::{MiCommands, Registers};
 use rand_core::{OsRng, RngCore};
 use anyhow::Result;
 use thiserror::Error;
+use btleplug::api::BDAddr;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
 
 type HmacSha256 = Hmac<Sha256>;
 type AesCcm = Ccm<Aes128, U4, U12>;
@@ -20,6 +25,8 @@ pub enum MiCryptoError {
   InvalidHeader,
   #[error("Error when tried decrypt uart message: {0}")]
   DecryptUart(ccm::aead::Error),
+  #[error("Invalid auth token: {0}")]
+  InvalidToken(String),
   #[error("Crypto Failure: {0}")]
   Other(anyhow::Error)
 }
@@ -88,6 +95,188 @@ fn hash(secret : &[u8], data: &[u8]) -> Hash {
 
 pub type AuthToken = [u8; 12];
 
+/**
+ * Lengths of key material produced during registration, token is always first 12 bytes:
+ * 28 = token + bind key, 44 = token + bind key + A key, 64 = whole HKDF output
+ */
+const TOKEN_BLOB_LENGTHS : [usize; 3] = [28, 44, 64];
+
+/**
+ * Import token saved by other tool. Login expects exactly 12 bytes, which are first 12 bytes of
+ * HKDF(shared_secret, info="mible-setup-info") calculated during registration. Accepted formats:
+ *   - raw 12 bytes, the same as .mi-token file written by register example
+ *   - raw registration key material (28, 44 or 64 bytes), token is sliced from the beginning
+ *   - any of the above as hex string, whitespace and newlines are ignored
+ */
+pub fn import_token(bytes: &[u8]) -> Result<AuthToken, MiCryptoError> {
+  let text : Vec<u8> = bytes.iter()
+    .filter(|byte| !byte.is_ascii_whitespace())
+    .copied()
+    .collect();
+
+  if text.len() >= 24 && text.iter().all(|byte| byte.is_ascii_hexdigit()) {
+    let decoded = hex::decode(&text)
+      .map_err(|err| MiCryptoError::InvalidToken(format!("Could not decode hex: {}", err)))?;
+    return import_token(&decoded)
+  }
+
+  let token : AuthToken = if bytes.len() == 12 || TOKEN_BLOB_LENGTHS.contains(&bytes.len()) {
+    bytes[0..12].try_into().unwrap()
+  } else {
+    return Err(MiCryptoError::InvalidToken(format!("Unexpected length: {} bytes", bytes.len())))
+  };
+
+  if token.iter().all(|byte| *byte == 0) {
+    return Err(MiCryptoError::InvalidToken("Token is all zeros".to_owned()))
+  }
+
+  Ok(token)
+}
+
+/**
+ * Export token as raw 12 bytes, the format expected by import_token and .mi-token file
+ */
+pub fn export_token(token: &AuthToken) -> Vec<u8> {
+  token.to_vec()
+}
+
+/**
+ * Framed token file: magic + version + token. Lets tools tell token files apart and keeps old files
+ * readable if token format ever changes
+ */
+const TOKEN_FILE_MAGIC : [u8; 4] = *b"MITK";
+const TOKEN_FILE_VERSION : u8 = 1;
+
+/**
+ * Serialize token in framed v1 format: "MITK", version 0x01, 12 bytes of token
+ */
+pub fn save_token_v1(token: &AuthToken) -> Vec<u8> {
+  let mut bytes = TOKEN_FILE_MAGIC.to_vec();
+  bytes.push(TOKEN_FILE_VERSION);
+  bytes.extend_from_slice(token);
+  bytes
+}
+
+/**
+ * Read token file contents, both framed v1 format and legacy raw 12 bytes are accepted
+ */
+pub fn load_token_v1(bytes: &[u8]) -> Result<AuthToken, MiCryptoError> {
+  if bytes.len() == 12 {
+    return Ok(bytes.try_into().unwrap())
+  }
+
+  if !bytes.starts_with(&TOKEN_FILE_MAGIC) {
+    return Err(MiCryptoError::InvalidToken(format!("Unknown token file format, {} bytes", bytes.len())))
+  }
+
+  match bytes.get(TOKEN_FILE_MAGIC.len()) {
+    Some(&TOKEN_FILE_VERSION) => {
+      let token = &bytes[TOKEN_FILE_MAGIC.len() + 1..];
+      token.try_into()
+        .map_err(|_| MiCryptoError::InvalidToken(format!("Expected 12 bytes of token, got {}", token.len())))
+    },
+    Some(version) => Err(MiCryptoError::InvalidToken(format!("Unsupported token file version: {}", version))),
+    None => Err(MiCryptoError::InvalidToken("Token file has no version".to_owned()))
+  }
+}
+
+/**
+ * Tokens of several scooters in one file, keyed by scooter address. File is flat TOML table with
+ * address as key and token as hex string, e.g.
+ *   "AA:BB:CC:DD:EE:FF" = "00112233445566778899aabb"
+ * Blank lines and lines starting with # are skipped
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenStore {
+  tokens: BTreeMap<BDAddr, AuthToken>,
+}
+
+impl TokenStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * Read store from file, missing file gives empty store so first registration can create it
+   */
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MiCryptoError> {
+    match std::fs::read_to_string(path.as_ref()) {
+      Ok(text) => Self::parse(&text),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::new()),
+      Err(err) => Err(anyhow::Error::from(err).into())
+    }
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MiCryptoError> {
+    std::fs::write(path.as_ref(), self.to_string())
+      .map_err(|err| anyhow::Error::from(err).into())
+  }
+
+  pub fn parse(text: &str) -> Result<Self, MiCryptoError> {
+    let mut store = Self::new();
+
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue
+      }
+
+      let invalid = |reason: &str| MiCryptoError::InvalidToken(format!("Line {}: {}", number + 1, reason));
+      let (addr, token) = line.split_once('=').ok_or_else(|| invalid("expected \"address\" = \"token\""))?;
+      let addr = BDAddr::from_str(unquote(addr)).map_err(|err| invalid(&err.to_string()))?;
+      let token = unquote(token);
+      if token.len() != 24 {
+        return Err(invalid(&format!("expected 24 hex digits of token, got {}", token.len())))
+      }
+
+      store.put(addr, import_token(token.as_bytes())?);
+    }
+
+    Ok(store)
+  }
+
+  pub fn get(&self, addr: &BDAddr) -> Option<&AuthToken> {
+    self.tokens.get(addr)
+  }
+
+  /**
+   * Add or replace token for scooter, returns previous one
+   */
+  pub fn put(&mut self, addr: BDAddr, token: AuthToken) -> Option<AuthToken> {
+    self.tokens.insert(addr, token)
+  }
+
+  pub fn remove(&mut self, addr: &BDAddr) -> Option<AuthToken> {
+    self.tokens.remove(addr)
+  }
+
+  pub fn len(&self) -> usize {
+    self.tokens.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.tokens.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&BDAddr, &AuthToken)> {
+    self.tokens.iter()
+  }
+}
+
+impl std::fmt::Display for TokenStore {
+  fn fmt(&self, form: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (addr, token) in &self.tokens {
+      writeln!(form, "\"{}\" = \"{}\"", addr, hex::encode(token))?;
+    }
+    Ok(())
+  }
+}
+
+fn unquote(value: &str) -> &str {
+  let value = value.trim();
+  value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value)
+}
+
 pub fn calc_did(my_secret_key: &EphemeralSecret, remote_key_bytes: &[u8], remote_info: &[u8]) -> (Vec<u8>, AuthToken) {
   let key_bytes = remote_key_bytes;
   tracing::debug!("Calculating did with remote key: {:?}", key_bytes.hex_dump());
@@ -203,77 +392,77 @@ pub fn gen_rand_key() -> RandKey {
 
 const HEADER : [u8; 2] = [0x55, 0xab];
 
+/**
+ * Length of CCM tag at the end of encrypted UART data (U4 of AesCcm)
+ */
+const UART_TAG_SIZE : usize = 4;
+
 pub fn encrypt_uart(encryption_key: &EncryptionKey, msg: &[u8], it : u32, rand: Option<[u8; 4]>) -> Vec<u8> {
+  let mut send_data : Vec<u8> = Vec::new();
+  encrypt_uart_into(encryption_key, msg, it, rand, &mut send_data);
+  send_data
+}
+
+/**
+ * Same as encrypt_uart, but frame is written into out (cleared first), so its allocation can be reused for every command
+ */
+pub fn encrypt_uart_into(encryption_key: &EncryptionKey, msg: &[u8], it : u32, rand: Option<[u8; 4]>, out: &mut Vec<u8>) {
   tracing::debug!("Encrypting UART");
 
   let it = it.to_be_bytes();
 
-  let rand = rand.or_else(|| {
+  let rand = rand.unwrap_or_else(|| {
     let mut rand : [u8; 4] = [0u8; 4];
     OsRng::fill_bytes(&mut OsRng, &mut rand);
-    Some(rand)
-  }).unwrap();
+    rand
+  });
 
   tracing::debug!("  rand: {:?}", rand.hex_dump());
   tracing::debug!("  it: {:?}", it.hex_dump());
   tracing::debug!("  message: {:?}", msg.hex_dump());
-  tracing::debug!("  iv: {:?}", encryption_key.iv.hex_dump());
-  tracing::debug!("  key: {:?}", encryption_key.key.hex_dump());
-
-  let size : &[u8] = &msg[0..1];
-  tracing::debug!("  size: {:?}", size.hex_dump());
-
-  let mut data : Vec<u8> = Vec::new();
-  data.extend_from_slice(&msg[1..]);
-  tracing::debug!("  data: {:?}", data.hex_dump());
-  data.extend_from_slice(&rand);
-  tracing::debug!("  data with rand: {:?}", data.hex_dump());
 
-  let mut nonce : Vec<u8> = Vec::new();
-  nonce.extend_from_slice(&encryption_key.iv);
-  for _ in 0..4 { nonce.push(0); }
-  nonce.extend_from_slice(&it);
+  let mut nonce = [0u8; 12];
+  nonce[0..4].copy_from_slice(&encryption_key.iv);
+  nonce[8..12].copy_from_slice(&it);
   tracing::debug!("  nonce: {:?}", nonce.hex_dump());
 
-  let key = GenericArray::from_slice(&encryption_key.key);
-  let nonce = GenericArray::from_slice(&nonce);
-  let aes_ccm = AesCcm::new(&key);
-
-  let ct = aes_ccm.encrypt(&nonce, data.as_slice())
-    .expect("Could not encrypt uart");// output 48 bytes
-
-  tracing::debug!("  CT: {:?}", ct.hex_dump());
+  // header, size and counter, followed by data with rand which is encrypted in place
+  out.clear();
+  out.extend_from_slice(&HEADER);
+  out.push(msg[0]);
+  out.extend_from_slice(&it[0..2]);
+  let data_start = out.len();
+  out.extend_from_slice(&msg[1..]);
+  out.extend_from_slice(&rand);
 
-  let mut send_data : Vec<u8> = Vec::new();
-  send_data.extend_from_slice(size);
-  send_data.extend_from_slice(&it[0..2]);
-  send_data.extend_from_slice(ct.as_slice());
-  tracing::debug!("  Send data: {:?}", send_data.hex_dump());
+  let key = GenericArray::from_slice(&encryption_key.key);
+  let aes_ccm = AesCcm::new(key);
+  let tag = aes_ccm.encrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], &mut out[data_start..])
+    .expect("Could not encrypt uart");
+  out.extend_from_slice(&tag);
 
-  let crc = crc16(send_data.as_slice()); // new checksum
+  let crc = crc16(&out[2..]); // new checksum
   tracing::debug!("  CRC: {:?}", crc.hex_dump());
+  out.extend_from_slice(&crc);
 
-  send_data.insert(0, HEADER[1]); // second header
-  send_data.insert(0, HEADER[0]); // new header starts here
-  send_data.extend_from_slice(&crc);
-
-  tracing::debug!("  Final data: {:?}", send_data.hex_dump());
-
-  send_data
+  tracing::debug!("  Final data: {:?}", out.hex_dump());
 }
 
 pub fn crc16(bytes: &[u8]) -> [u8; 2] {
-  let mut sum : i16 = 0;
-  for byte in bytes {
-    sum += *byte as i16;
-  }
-
-  let mut res = (-(sum) - 1).to_be_bytes();
-  res.reverse();
-  res
+  crate::protocol::xiaomi_checksum(bytes).to_le_bytes()
 }
 
 pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8>, MiCryptoError> {
+  let mut data : Vec<u8> = Vec::new();
+  decrypt_uart_into(encryption_key, msg, &mut data)?;
+  Ok(data)
+}
+
+/**
+ * Same as decrypt_uart, but decrypted data is written into out (cleared first), so high rate polling can reuse
+ * one buffer instead of allocating for every response
+ */
+pub fn decrypt_uart_into(encryption_key: &EncryptionKey, msg: &[u8], out: &mut Vec<u8>) -> Result<(), MiCryptoError> {
   tracing::debug!("  Decrypting data: {:?}", msg.hex_dump());
   let header = &msg[0..2];
 
@@ -287,33 +476,373 @@ pub fn decrypt_uart(encryption_key: &EncryptionKey, msg: &[u8]) -> Result<Vec<u8
 
   tracing::debug!("  it: {:?}", it.hex_dump());
   tracing::debug!("  ct: {:?}", ct.hex_dump());
-  tracing::debug!("  key: {:?}", encryption_key.key.hex_dump());
 
-  let mut nonce : Vec<u8> = Vec::new();
-  nonce.extend_from_slice(&encryption_key.iv);
-  for _ in 0..4 { nonce.push(0); }
-  nonce.extend_from_slice(it);
-  for _ in 0..2 { nonce.push(0); }
+  let mut nonce = [0u8; 12];
+  nonce[0..4].copy_from_slice(&encryption_key.iv);
+  nonce[8..10].copy_from_slice(it);
   tracing::debug!("  nonce: {:?}", nonce.hex_dump());
 
+  let (ct, tag) = ct.split_at(ct.len().saturating_sub(UART_TAG_SIZE));
+  out.clear();
+  out.extend_from_slice(ct);
+
   let key = GenericArray::from_slice(&encryption_key.key);
-  let nonce = GenericArray::from_slice(&nonce);
   let aes_ccm = AesCcm::new(key);
 
   tracing::debug!("Decrypting...");
 
-  let data = match aes_ccm.decrypt(nonce, Payload {
-    msg: ct,
-    aad: &[], //of course returned aad is empty array, because fuck you thats why...
-  }) {
-    Ok(data) => data,
-    Err(err) => {
-      tracing::error!("Decryption error: {}", err);
-      return Err(MiCryptoError::DecryptUart(err))
+  if tag.len() != UART_TAG_SIZE {
+    tracing::error!("Decryption error: message is too short");
+    return Err(MiCryptoError::DecryptUart(ccm::aead::Error))
+  }
+  if let Err(err) = aes_ccm.decrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], out, GenericArray::from_slice(tag)) {
+    tracing::error!("Decryption error: {}", err);
+    return Err(MiCryptoError::DecryptUart(err))
+  }
+
+  tracing::debug!("  Decrypted data: {:?}", out.hex_dump());
+
+  Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum MiAuthError {
+  #[error("Expected {expected} while {stage}, but scooter sent: {received}")]
+  UnexpectedResponse { stage: &'static str, expected: &'static str, received: String },
+  #[error("Scooter sent invalid remote key")]
+  InvalidDid,
+  #[error("Scooter rejected registration")]
+  RegistrationFailed,
+  #[error("Scooter rejected login")]
+  LoginFailed,
+  #[error("Handshake is already finished")]
+  Finished,
+}
+
+/**
+ * What driver has to read after executing OutgoingCmd, before calling MiAuthStateMachine::step
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expect {
+  /**
+   * Call step right away with empty response
+   */
+  Nothing,
+  /**
+   * Single notification, e.g. RCV_RDY or RCV_OK
+   */
+  Notification,
+  /**
+   * Whole mi parcel, read from register of command (see MiProtocol::read_mi_parcel)
+   */
+  Parcel,
+}
+
+/**
+ * Next thing to do in MiAuth handshake
+ */
+#[derive(Debug)]
+pub enum OutgoingCmd {
+  /**
+   * Write control command to register
+   */
+  Command { register: Registers, command: MiCommands, expect: Expect },
+  /**
+   * Write data as mi parcel, split into numbered chunks
+   */
+  Parcel { register: Registers, data: Vec<u8>, expect: Expect },
+  /**
+   * Write nothing, only wait for response
+   */
+  Wait { register: Registers, expect: Expect },
+}
+
+impl OutgoingCmd {
+  pub fn register(&self) -> &Registers {
+    match self {
+      OutgoingCmd::Command { register, .. } |
+      OutgoingCmd::Parcel { register, .. } |
+      OutgoingCmd::Wait { register, .. } => register
     }
-  };
+  }
 
-  tracing::debug!("  Decrypted data: {:?}", data.hex_dump());
+  pub fn expect(&self) -> Expect {
+    match self {
+      OutgoingCmd::Command { expect, .. } |
+      OutgoingCmd::Parcel { expect, .. } |
+      OutgoingCmd::Wait { expect, .. } => *expect
+    }
+  }
+}
 
-  Ok(data)
+/**
+ * Stage is named by response the state machine waits for
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+  Idle,
+  // registration
+  RemoteInfo,
+  KeySet,
+  ReadyForPublicKey,
+  PublicKeyReceived,
+  RemoteKey,
+  ReadyForDid,
+  DidReceived,
+  Authenticated,
+  // login
+  LoginStarted,
+  ReadyForRandKey,
+  RandKeyReceived,
+  LoginRemoteKey,
+  LoginRemoteInfo,
+  ReadyForInfo,
+  InfoReceived,
+  LoggedIn,
+  Done,
+}
+
+enum Flow {
+  Registration {
+    secret_key: EphemeralSecret,
+    public_key: PublicKey,
+    remote_info: Option<Vec<u8>>,
+    did: Option<Vec<u8>>,
+    token: Option<AuthToken>,
+  },
+  Login {
+    token: AuthToken,
+    rand_key: RandKey,
+    remote_key: Option<Vec<u8>>,
+    info: Option<Hash>,
+    keys: Option<LoginKeychain>,
+  },
+}
+
+/**
+ * MiAuth handshake without any bluetooth, so it can be driven by MiProtocol (RegistrationRequest, LoginRequest)
+ * or by hand. Registration: GET_INFO -> SET_KEY -> SEND_DATA (public key) -> SEND_DID -> AUTH.
+ * Login: LOGIN -> SEND_KEY (rand key) -> remote key and info -> SEND_INFO -> login result.
+ *
+ * Driver executes command returned by start / step, reads response described by OutgoingCmd::expect and
+ * passes it to step, until step returns None.
+ */
+pub struct MiAuthStateMachine {
+  flow: Flow,
+  stage: Stage,
+}
+
+impl MiAuthStateMachine {
+  /**
+   * Registration with new key pair, gives AuthToken when finished
+   */
+  pub fn register() -> Self {
+    let (secret_key, public_key) = gen_key_pair();
+    Self {
+      flow: Flow::Registration { secret_key, public_key, remote_info: None, did: None, token: None },
+      stage: Stage::Idle
+    }
+  }
+
+  /**
+   * Login with token from registration, gives LoginKeychain when finished
+   */
+  pub fn login(token: &AuthToken) -> Self {
+    Self::login_with_rand_key(token, gen_rand_key())
+  }
+
+  pub fn login_with_rand_key(token: &AuthToken, rand_key: RandKey) -> Self {
+    Self {
+      flow: Flow::Login { token: *token, rand_key, remote_key: None, info: None, keys: None },
+      stage: Stage::Idle
+    }
+  }
+
+  /**
+   * First command of handshake
+   */
+  pub fn start(&mut self) -> OutgoingCmd {
+    match self.flow {
+      Flow::Registration { .. } => {
+        self.stage = Stage::RemoteInfo;
+        OutgoingCmd::Command { register: Registers::UPNP, command: MiCommands::CMD_GET_INFO, expect: Expect::Parcel }
+      },
+      Flow::Login { .. } => {
+        self.stage = Stage::LoginStarted;
+        OutgoingCmd::Command { register: Registers::UPNP, command: MiCommands::CMD_LOGIN, expect: Expect::Nothing }
+      }
+    }
+  }
+
+  /**
+   * Pass response of last command, returns next command or None when handshake finished
+   */
+  pub fn step(&mut self, response: &[u8]) -> Result<Option<OutgoingCmd>, MiAuthError> {
+    tracing::debug!("MiAuth {:?} <- {:?}", self.stage, response.hex_dump());
+
+    let (stage, next) = match (self.stage, &mut self.flow) {
+      (Stage::Idle, _) => return Ok(Some(self.start())),
+      (Stage::Done, _) => return Err(MiAuthError::Finished),
+
+      (Stage::RemoteInfo, Flow::Registration { remote_info, .. }) => {
+        *remote_info = Some(response.to_vec());
+        (Stage::KeySet, OutgoingCmd::Command { register: Registers::UPNP, command: MiCommands::CMD_SET_KEY, expect: Expect::Nothing })
+      },
+      (Stage::KeySet, Flow::Registration { .. }) => {
+        (Stage::ReadyForPublicKey, OutgoingCmd::Command { register: Registers::AVDTP, command: MiCommands::CMD_SEND_DATA, expect: Expect::Notification })
+      },
+      (Stage::ReadyForPublicKey, Flow::Registration { public_key, .. }) => {
+        expect_response(response, MiCommands::RCV_RDY, "uploading public key")?;
+        let public_key_bytes = EncodedPoint::from(*public_key);
+        (Stage::PublicKeyReceived, OutgoingCmd::Parcel { register: Registers::AVDTP, data: public_key_bytes.as_bytes()[1..].to_vec(), expect: Expect::Notification })
+      },
+      (Stage::PublicKeyReceived, Flow::Registration { .. }) => {
+        expect_response(response, MiCommands::RCV_OK, "uploading public key")?;
+        (Stage::RemoteKey, OutgoingCmd::Wait { register: Registers::AVDTP, expect: Expect::Parcel })
+      },
+      (Stage::RemoteKey, Flow::Registration { secret_key, remote_info, did, token, .. }) => {
+        let remote_key_bytes = [&[0x04], response].concat();
+        let (did_ct, new_token) = calc_did(secret_key, &remote_key_bytes, remote_info.as_deref().unwrap_or_default());
+        *did = Some(did_ct);
+        *token = Some(new_token);
+        (Stage::ReadyForDid, OutgoingCmd::Command { register: Registers::AVDTP, command: MiCommands::CMD_SEND_DID, expect: Expect::Notification })
+      },
+      (Stage::ReadyForDid, Flow::Registration { did, .. }) => {
+        expect_response(response, MiCommands::RCV_RDY, "sending did")?;
+        (Stage::DidReceived, OutgoingCmd::Parcel { register: Registers::AVDTP, data: did.clone().unwrap_or_default(), expect: Expect::Notification })
+      },
+      (Stage::DidReceived, Flow::Registration { .. }) => {
+        expect_response(response, MiCommands::RCV_OK, "sending did")?;
+        (Stage::Authenticated, OutgoingCmd::Command { register: Registers::UPNP, command: MiCommands::CMD_AUTH, expect: Expect::Notification })
+      },
+      (Stage::Authenticated, Flow::Registration { .. }) => {
+        match MiCommands::from_response(response) {
+          Some(MiCommands::RCV_AUTH_OK) => {
+            self.stage = Stage::Done;
+            return Ok(None)
+          },
+          _ => return Err(MiAuthError::RegistrationFailed)
+        }
+      },
+
+      (Stage::LoginStarted, Flow::Login { .. }) => {
+        (Stage::ReadyForRandKey, OutgoingCmd::Command { register: Registers::AVDTP, command: MiCommands::CMD_SEND_KEY, expect: Expect::Notification })
+      },
+      (Stage::ReadyForRandKey, Flow::Login { rand_key, .. }) => {
+        expect_response(response, MiCommands::RCV_RDY, "sending rand key")?;
+        (Stage::RandKeyReceived, OutgoingCmd::Parcel { register: Registers::AVDTP, data: rand_key.to_vec(), expect: Expect::Notification })
+      },
+      (Stage::RandKeyReceived, Flow::Login { .. }) => {
+        expect_response(response, MiCommands::RCV_OK, "sending rand key")?;
+        (Stage::LoginRemoteKey, OutgoingCmd::Wait { register: Registers::AVDTP, expect: Expect::Parcel })
+      },
+      (Stage::LoginRemoteKey, Flow::Login { remote_key, .. }) => {
+        *remote_key = Some(response.to_vec());
+        (Stage::LoginRemoteInfo, OutgoingCmd::Wait { register: Registers::AVDTP, expect: Expect::Parcel })
+      },
+      (Stage::LoginRemoteInfo, Flow::Login { token, rand_key, remote_key, info, keys }) => {
+        let mut remote_key = remote_key.clone().unwrap_or_default();
+        let (login_info, expected_remote_info, login_keys) = calc_login_did(rand_key, &mut remote_key, token);
+
+        if response != expected_remote_info {
+          tracing::error!("Scooter send invalid remote key:");
+          tracing::error!("   Expected: {:?}", expected_remote_info.hex_dump());
+          tracing::error!("   Received: {:?}", response.hex_dump());
+          return Err(MiAuthError::InvalidDid)
+        }
+
+        tracing::debug!("Remote info is as expected, sending did");
+        *info = Some(login_info);
+        *keys = Some(login_keys);
+        (Stage::ReadyForInfo, OutgoingCmd::Command { register: Registers::AVDTP, command: MiCommands::CMD_SEND_INFO, expect: Expect::Notification })
+      },
+      (Stage::ReadyForInfo, Flow::Login { info, .. }) => {
+        expect_response(response, MiCommands::RCV_RDY, "sending did")?;
+        (Stage::InfoReceived, OutgoingCmd::Parcel { register: Registers::AVDTP, data: info.map(|info| info.to_vec()).unwrap_or_default(), expect: Expect::Notification })
+      },
+      (Stage::InfoReceived, Flow::Login { .. }) => {
+        expect_response(response, MiCommands::RCV_OK, "sending did")?;
+        (Stage::LoggedIn, OutgoingCmd::Wait { register: Registers::AVDTP, expect: Expect::Notification })
+      },
+      (Stage::LoggedIn, Flow::Login { .. }) => {
+        match MiCommands::from_response(response) {
+          Some(MiCommands::RCV_LOGIN_OK) => {
+            tracing::info!("Logged in!");
+            self.stage = Stage::Done;
+            return Ok(None)
+          },
+          _ => return Err(MiAuthError::LoginFailed)
+        }
+      },
+
+      (stage, _) => unreachable!("Stage {:?} does not belong to this flow", stage)
+    };
+
+    tracing::debug!("MiAuth {:?} -> {:?}", stage, next);
+    self.stage = stage;
+    Ok(Some(next))
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.stage == Stage::Done
+  }
+
+  /**
+   * Token from finished registration
+   */
+  pub fn token(&self) -> Option<AuthToken> {
+    match (&self.flow, self.is_finished()) {
+      (Flow::Registration { token, .. }, true) => *token,
+      _ => None
+    }
+  }
+
+  /**
+   * Device id (DID) of scooter, e.g. "blt.3.16394t3g4lc00", the same id official app shows. Scooter sends it
+   * only in answer to GET_INFO of registration, so it is known once remote info was received and never by login
+   */
+  pub fn device_id(&self) -> Option<String> {
+    match &self.flow {
+      Flow::Registration { remote_info: Some(remote_info), .. } => parse_device_id(remote_info),
+      _ => None
+    }
+  }
+
+  /**
+   * Session keys from finished login
+   */
+  pub fn keys(&self) -> Option<&LoginKeychain> {
+    match (&self.flow, self.is_finished()) {
+      (Flow::Login { keys, .. }, true) => keys.as_ref(),
+      _ => None
+    }
+  }
+}
+
+/**
+ * Remote info starts with 4 bytes of header, rest is DID padded with zeros
+ */
+fn parse_device_id(remote_info: &[u8]) -> Option<String> {
+  let did = remote_info.get(4..)?;
+  let did = std::str::from_utf8(did).ok()?.trim_matches('\0');
+
+  if did.is_empty() || !did.chars().all(|c| c.is_ascii_graphic()) {
+    return None
+  }
+  Some(did.to_owned())
+}
+
+fn expect_response(response: &[u8], expected: MiCommands, stage: &'static str) -> Result<(), MiAuthError> {
+  match MiCommands::from_response(response) {
+    Some(received) if std::mem::discriminant(&received) == std::mem::discriminant(&expected) => Ok(()),
+    _ => Err(MiAuthError::UnexpectedResponse {
+      stage,
+      expected: match expected {
+        MiCommands::RCV_RDY => "RCV_RDY",
+        MiCommands::RCV_OK => "RCV_OK",
+        _ => "response"
+      },
+      received: format!("{:02x?}", response)
+    })
+  }
 }
@@ -0,0 +1,173 @@
+use crate::protocol::Protocol;
+use crate::protocol_core::{Attribute, ReadWrite};
+
+use core::fmt::Debug;
+use pretty_hex::*;
+use thiserror::Error;
+
+/**
+ * Session Response types expect decrypted Mi frames, which end with 4 random bytes
+ */
+const RESPONSE_PADDING : [u8; 4] = [0; 4];
+
+/**
+ * Response to read command, writes are acknowledged with 0x05
+ */
+const READ_RESPONSE : u8 = 0x04;
+
+#[derive(Debug, Error)]
+pub enum NinebotError {
+  #[error("Invalid Ninebot frame: {0:02X?}")]
+  InvalidFrame(Vec<u8>),
+  #[error("Expected response of 0x{expected:02x} for attribute 0x{attribute:02x}, received attribute 0x{received:02x} from 0x{source_address:02x}")]
+  UnexpectedResponse { expected: u8, attribute: u8, source_address: u8, received: u8 },
+}
+
+/**
+ * Bus address of Ninebot ES / Max / G30 frames. Unlike M365 direction byte, every frame carries both source
+ * and destination
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+  /**
+   * Motor controller (ESC)
+   */
+  Esc,
+  /**
+   * Internal battery pack
+   */
+  Bms,
+  /**
+   * Phone app, source of every command sent by this library
+   */
+  App,
+}
+
+impl Address {
+  pub fn value(&self) -> u8 {
+    match self {
+      Address::Esc => 0x20,
+      Address::Bms => 0x22,
+      Address::App => 0x3E,
+    }
+  }
+
+  /**
+   * Controller holding attribute, same split as on M365: battery values are read from BMS
+   */
+  pub fn of(attribute: &Attribute) -> Address {
+    match attribute {
+      Attribute::BatteryVoltage |
+      Attribute::BatteryCurrent |
+      Attribute::BatteryPercent |
+      Attribute::BatteryCellVoltages |
+      Attribute::BatteryInfo => Address::Bms,
+      _ => Address::Esc
+    }
+  }
+}
+
+/**
+ * Command in 0x5A A5 framing: 5A A5 len src dst cmd attr payload ck ck, len is payload length.
+ * Frames are sent in plain text, this protocol has no encryption
+ */
+#[derive(Clone)]
+pub struct NinebotCommand {
+  pub destination: Address,
+  pub read_write: ReadWrite,
+  pub attribute: Attribute,
+  pub payload: Vec<u8>
+}
+
+impl NinebotCommand {
+  /**
+   * Read len bytes of attribute from controller which holds it
+   */
+  pub fn read(attribute: Attribute, len: u8) -> Self {
+    Self { destination: Address::of(&attribute), read_write: ReadWrite::Read, attribute, payload: vec![len] }
+  }
+
+  /**
+   * Complete frame with header and checksum
+   */
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let command = match self.read_write {
+      ReadWrite::Read  => 0x01,
+      ReadWrite::Write => 0x03,
+    };
+
+    let mut body = vec![self.payload.len() as u8, Address::App.value(), self.destination.value(), command, self.attribute.value()];
+    body.extend_from_slice(&self.payload);
+
+    let mut bytes = Protocol::Ninebot.header().to_vec();
+    bytes.extend_from_slice(&body);
+    bytes.extend_from_slice(&Protocol::Ninebot.checksum(&body).to_le_bytes());
+    bytes
+  }
+}
+
+impl Debug for NinebotCommand {
+  fn fmt(&self, form: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+    form.write_str(&format!("{:?}", self.as_bytes().hex_dump()))
+  }
+}
+
+/**
+ * Frame received from scooter, checked for header, length and checksum
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NinebotFrame {
+  pub source: u8,
+  pub destination: u8,
+  pub command: u8,
+  pub attribute: u8,
+  pub payload: Vec<u8>,
+}
+
+impl NinebotFrame {
+  pub fn parse(bytes: &[u8]) -> Result<Self, NinebotError> {
+    if Protocol::detect(bytes) != Some(Protocol::Ninebot) {
+      return Err(NinebotError::InvalidFrame(bytes.to_vec()))
+    }
+
+    Ok(Self {
+      source: bytes[3],
+      destination: bytes[4],
+      command: bytes[5],
+      attribute: bytes[6],
+      payload: bytes[7..bytes.len() - 2].to_vec(),
+    })
+  }
+
+  /**
+   * Check that frame answers read of command
+   */
+  pub fn expect_response(&self, command: &NinebotCommand) -> Result<(), NinebotError> {
+    let answers = self.source == command.destination.value() &&
+      self.destination == Address::App.value() &&
+      self.command == READ_RESPONSE &&
+      self.attribute == command.attribute.value();
+
+    if answers {
+      Ok(())
+    } else {
+      Err(NinebotError::UnexpectedResponse {
+        expected: command.destination.value(),
+        attribute: command.attribute.value(),
+        source_address: self.source,
+        received: self.attribute,
+      })
+    }
+  }
+
+  /**
+   * Frame in layout of decrypted M365 response (attribute is third byte, payload follows), so every
+   * session Response implementation can decode it
+   */
+  pub fn response_bytes(&self) -> Vec<u8> {
+    let mut bytes = vec![self.source, self.command, self.attribute];
+    bytes.extend_from_slice(&self.payload);
+    bytes.extend_from_slice(&RESPONSE_PADDING);
+    bytes
+  }
+}
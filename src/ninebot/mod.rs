@@ -0,0 +1,4 @@
+mod frame;
+mod ninebot_session;
+pub use frame::{Address, NinebotCommand, NinebotFrame, NinebotError};
+pub use ninebot_session::NinebotSession;
@@ -0,0 +1,132 @@
+use super::frame::{NinebotCommand, NinebotFrame};
+
+use crate::clone_connection::ScooterConnection;
+use crate::protocol::Protocol;
+use crate::protocol_core::Attribute;
+use crate::session::{Response, MotorInfo, BatteryInfo};
+
+use btleplug::platform::Peripheral;
+use anyhow::{Result, anyhow};
+
+/**
+ * Session with Ninebot ES / Max / G30 scooter over 0x5A A5 protocol. Frames are not encrypted and need no
+ * login, so this uses plain UART transport of ScooterConnection instead of MiSession.
+ * Registers read here are at the same addresses as on M365 and decode into the same types
+ * (MotorInfo, BatteryInfo), what differs is framing, addressing and serial number layout
+ */
+pub struct NinebotSession {
+  connection: ScooterConnection,
+}
+
+impl NinebotSession {
+  /**
+   * Connect to scooter and use Ninebot framing from the first command
+   */
+  pub async fn new(device: &Peripheral) -> Result<Self> {
+    let connection = ScooterConnection::connect(device, false).await?;
+    Ok(Self::from_connection(connection))
+  }
+
+  pub fn from_connection(connection: ScooterConnection) -> Self {
+    connection.set_protocol(Protocol::Ninebot);
+    Self { connection }
+  }
+
+  pub fn connection(&self) -> &ScooterConnection {
+    &self.connection
+  }
+
+  /**
+   * Send command and return response frame after checking that it answers command
+   */
+  #[tracing::instrument(skip(self, cmd), fields(attribute = ?cmd.attribute))]
+  pub async fn send(&mut self, cmd: &NinebotCommand) -> Result<NinebotFrame> {
+    let response = self.connection.transaction(&cmd.as_bytes()).await?;
+    let frame = NinebotFrame::parse(&response)?;
+    frame.expect_response(cmd)?;
+    Ok(frame)
+  }
+
+  /**
+   * Send command and decode response into any type implementing Response trait
+   */
+  pub async fn request<T: Response>(&mut self, cmd: &NinebotCommand) -> Result<T> {
+    let frame = self.send(cmd).await?;
+    T::parse(cmd.attribute.clone(), &frame.response_bytes())
+  }
+
+  /**
+   * Read len bytes of any attribute, from ESC or BMS depending on attribute (see Address::of)
+   */
+  pub async fn read_attribute(&mut self, attribute: Attribute, len: u8) -> Result<Vec<u8>> {
+    let frame = self.send(&NinebotCommand::read(attribute.clone(), len)).await?;
+
+    frame.payload.get(..len as usize)
+      .map(|payload| payload.to_vec())
+      .ok_or_else(|| anyhow!("Expected {} bytes of {:?}, received {}", len, attribute, frame.payload.len()))
+  }
+
+  async fn read_u16(&mut self, attribute: Attribute) -> Result<u16> {
+    let bytes = self.read_attribute(attribute, 2).await?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+  }
+
+  /**
+   * Serial number, 14 characters starting with N (e.g. N4GSD1234C5678). Unlike M365 it is not followed by pin
+   */
+  pub async fn serial_number(&mut self) -> Result<String> {
+    tracing::debug!("Reading serial number");
+
+    let bytes = self.read_attribute(Attribute::GeneralInfo, 14).await?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+  }
+
+  /**
+   * ESC firmware version, one nibble per digit like on M365, e.g. 0x0155 is 1.5.5
+   */
+  pub async fn firmware_version(&mut self) -> Result<String> {
+    tracing::debug!("Reading firmware version");
+
+    let version = self.read_u16(Attribute::FirmwareVersion).await?;
+    Ok(format!("{}.{}.{}", (version >> 8) & 0x0f, (version >> 4) & 0x0f, version & 0x0f))
+  }
+
+  pub async fn motor_info(&mut self) -> Result<MotorInfo> {
+    tracing::debug!("Reading motor info");
+    self.request(&NinebotCommand::read(Attribute::MotorInfo, 0x20)).await
+  }
+
+  pub async fn battery_info(&mut self) -> Result<BatteryInfo> {
+    tracing::debug!("Reading battery info");
+    self.request(&NinebotCommand::read(Attribute::BatteryInfo, 0x0A)).await
+  }
+
+  /**
+   * Battery voltage in volts
+   */
+  pub async fn battery_voltage(&mut self) -> Result<f32> {
+    tracing::debug!("Reading battery voltage");
+    Ok(self.read_u16(Attribute::BatteryVoltage).await? as f32 / 100.0)
+  }
+
+  pub async fn battery_percentage(&mut self) -> Result<f32> {
+    tracing::debug!("Reading battery percentage");
+    Ok(self.read_u16(Attribute::BatteryPercent).await? as f32)
+  }
+
+  /**
+   * Travel distance left in kilometers
+   */
+  pub async fn distance_left(&mut self) -> Result<f32> {
+    tracing::debug!("Reading distance left");
+    Ok(self.read_u16(Attribute::DistanceLeft).await? as f32 / 100.0)
+  }
+
+  /**
+   * Temperature of motor controller in celsius
+   */
+  pub async fn controller_temperature(&mut self) -> Result<f32> {
+    tracing::debug!("Reading controller temperature");
+    Ok(self.read_u16(Attribute::ControllerTemp).await? as i16 as f32 / 10.0)
+  }
+}
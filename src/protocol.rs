@@ -1,18 +1,29 @@
 use crate::consts::{MiCommands, Registers};
+use crate::mi_crypto::{MiAuthStateMachine, OutgoingCmd, Expect};
+use crate::session::SessionError;
 use uuid::Uuid;
 use futures::Stream;
 use futures::stream::StreamExt;
 use pretty_hex::*;
 use std::{pin::Pin, boxed::Box};
 use btleplug::platform::{Peripheral};
-use tokio::time::timeout;
+use crate::util::with_timeout;
 use std::time::Duration;
-use btleplug::api::{Peripheral as _, Characteristic, WriteType, ValueNotification};
+use btleplug::api::{Peripheral as _, Characteristic, CharPropFlags, WriteType, ValueNotification};
 use anyhow::{Context, Result, anyhow};
 
+pub use crate::protocol_core::{xiaomi_checksum, ninebot_checksum, Protocol, FrameAssembler};
+
+pub mod replay;
+
 const NB_CHUNK_SIZE : usize = 20;
 const MI_CHUNK_SIZE : usize = 18;
 
+/**
+ * Default time to wait for every notification of ninebot response
+ */
+pub const NB_NOTIFICATION_TIMEOUT : Duration = Duration::from_secs(5);
+
 /**
  * This structs hides all bluetooth shenanigans under easy to use commands.
  */
@@ -23,11 +34,20 @@ pub struct MiProtocol {
   tx: Characteristic,
   rx: Characteristic,
   stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+  write_type: Option<WriteType>,
 }
 
 impl MiProtocol {
   pub async fn new(device: &Peripheral) -> Result<Self> {
-    let (avdtp, upnp, tx, rx) = setup_channels(&device).await?;
+    Self::with_skip_discovery(device, false).await
+  }
+
+  /**
+   * Same as `new`, but with skip_discovery trusts characteristics cached from earlier connection instead of
+   * discovering services again. Discovery still runs when expected characteristic is not cached
+   */
+  pub async fn with_skip_discovery(device: &Peripheral, skip_discovery: bool) -> Result<Self> {
+    let (avdtp, upnp, tx, rx) = setup_channels(&device, skip_discovery).await?;
     let stream : Pin<Box<dyn Stream<Item = ValueNotification> + Send>> = device.notifications().await
       .with_context(|| format!("Could not load notifications stream"))?;
     let device = device.clone();
@@ -38,20 +58,106 @@ impl MiProtocol {
       avdtp,
       upnp,
       tx,
-      rx
+      rx,
+      write_type: None
     };
 
     Ok(instance)
   }
 
+  pub fn device(&self) -> &Peripheral {
+    &self.device
+  }
+
+  /**
+   * Enable notifications of AVDTP, UPNP and RX again after dispose
+   */
+  pub async fn subscribe(&self) -> Result<()> {
+    for channel in self.notify_channels() {
+      self.device.subscribe(&channel).await
+        .with_context(|| format!("Could not subscribe to {} notifications", channel.uuid))?;
+    }
+
+    Ok(())
+  }
+
+  /**
+   * Subscribe again and take new notification stream. Subscriptions don't survive link drop, so after scooter
+   * comes back old stream can stay silent although writes still work
+   */
+  pub async fn resubscribe(&mut self) -> Result<()> {
+    self.subscribe().await?;
+    self.stream = self.device.notifications().await
+      .with_context(|| "Could not load notifications stream")?;
+    Ok(())
+  }
+
+  /**
+   * Disable notifications. Dropping protocol does not do it, platforms may keep subscriptions alive
+   * for the next connection
+   */
   pub async fn dispose(&self) -> Result<bool> {
-    self.device.unsubscribe(&self.avdtp).await?;
-    self.device.unsubscribe(&self.upnp).await?;
-    self.device.unsubscribe(&self.rx).await?;
+    for channel in self.notify_channels() {
+      self.device.unsubscribe(&channel).await?;
+    }
 
     Ok(true)
   }
 
+  fn notify_channels(&self) -> Vec<Characteristic> {
+    vec![self.avdtp.clone(), self.upnp.clone(), self.rx.clone()]
+  }
+
+  /**
+   * Force write type for every write. By default it is picked from characteristic properties
+   */
+  pub fn set_write_type(&mut self, write_type: WriteType) {
+    self.write_type = Some(write_type);
+  }
+
+  /**
+   * Use other characteristics for UART traffic, e.g. clones which speak M365 protocol over FE95 0x10/0x19.
+   * Characteristics are looked up by uuid in any service, new RX is subscribed for notifications
+   */
+  pub async fn set_uart_characteristics(&mut self, tx_uuid: Uuid, rx_uuid: Uuid) -> Result<()> {
+    let characteristics = self.device.characteristics();
+    let find = |uuid: Uuid| characteristics.iter()
+      .find(|ch| ch.uuid == uuid)
+      .cloned()
+      .ok_or_else(|| anyhow!("Could not find characteristic: {}", uuid));
+
+    let tx = find(tx_uuid)?;
+    let rx = find(rx_uuid)?;
+
+    if rx.uuid != self.rx.uuid {
+      if let Err(err) = self.device.unsubscribe(&self.rx).await {
+        tracing::debug!("Could not unsubscribe old RX: {}", err);
+      }
+
+      if rx.uuid != self.avdtp.uuid && rx.uuid != self.upnp.uuid {
+        tracing::debug!("Enabling notify for {}", rx.uuid);
+        self.device.subscribe(&rx).await
+          .with_context(|| format!("Could not subscribe to {} notifications", rx.uuid))?;
+      }
+    }
+
+    self.tx = tx;
+    self.rx = rx;
+    Ok(())
+  }
+
+  fn write_type_for(&self, channel: &Characteristic) -> WriteType {
+    if let Some(write_type) = self.write_type {
+      return write_type
+    }
+
+    if channel.properties.contains(CharPropFlags::WRITE_WITHOUT_RESPONSE) {
+      WriteType::WithoutResponse
+    } else {
+      WriteType::WithResponse
+    }
+  }
+
   fn reg_to_channel(&self, reg : &Registers) -> Option<&Characteristic> {
     match reg {
       Registers::RX => Some(&self.rx),
@@ -86,6 +192,39 @@ impl MiProtocol {
     }
   }
 
+  /**
+   * Execute single step of MiAuth handshake and read response it expects
+   */
+  pub async fn execute_auth_cmd(&mut self, cmd: &OutgoingCmd) -> Result<Vec<u8>> {
+    match cmd {
+      OutgoingCmd::Command { register, command, .. } => self.write(register, command.clone()).await?,
+      OutgoingCmd::Parcel { register, data, .. } => self.write_mi_parcel(register, data).await?,
+      OutgoingCmd::Wait { .. } => true
+    };
+
+    match cmd.expect() {
+      Expect::Nothing => Ok(Vec::new()),
+      Expect::Notification => Ok(self.wait_for_notification().await?.value),
+      Expect::Parcel => self.read_mi_parcel(cmd.register()).await
+    }
+  }
+
+  /**
+   * Drive MiAuth handshake until it is finished. Errors of state machine are MiAuthError,
+   * so they can be downcasted to tell rejected token from broken connection
+   */
+  pub async fn run_handshake(&mut self, machine: &mut MiAuthStateMachine) -> Result<()> {
+    let mut next = Some(machine.start());
+
+    while let Some(cmd) = next {
+      tracing::debug!("-> {:?}", cmd);
+      let response = self.execute_auth_cmd(&cmd).await?;
+      next = machine.step(&response)?;
+    }
+
+    Ok(())
+  }
+
   /**
    * Try to read next notification as MiCommand response
    */
@@ -106,7 +245,7 @@ impl MiProtocol {
    * Try to read next notification, If nothing comes in specified duration throw error
    */
   pub async fn wait_for_notification_with_timeout(&mut self, duration : Duration) -> Result<ValueNotification> {
-    let response = timeout(duration, self.next()).await?;//TODO: map to timeout error
+    let response = with_timeout(self.next(), duration).await?;
 
     if let Some(notification) = response {
       return Ok(notification)
@@ -122,6 +261,20 @@ impl MiProtocol {
     self.wait_for_notification_with_timeout(Duration::from_secs(10)).await
   }
 
+  /**
+   * Throw away notifications which are already waiting in stream, e.g. late response for command that timed out.
+   * Returns number of discarded notifications
+   */
+  pub async fn discard_pending(&mut self, quiet: Duration) -> usize {
+    let mut discarded = 0;
+    while let Ok(Some(notification)) = with_timeout(self.next(), quiet).await {
+      tracing::debug!("Discarding notification: {:?}", notification.value.hex_dump());
+      discarded += 1;
+    }
+
+    discarded
+  }
+
   /**
    * Send mi command to register on scooter
    */
@@ -129,7 +282,7 @@ impl MiProtocol {
     let channel = self.reg_to_channel(reg).unwrap();
     tracing::debug!("-> {:?} -> {:?}", command, &reg);
 
-    self.device.write(&channel, &command.to_bytes(), WriteType::WithoutResponse).await
+    self.device.write(&channel, &command.to_bytes(), self.write_type_for(channel)).await
       .with_context(|| format!("Could not write command: {:?} to {:?}", command, &reg))?;
 
     Ok(true)
@@ -139,21 +292,43 @@ impl MiProtocol {
    * Ninebot protocol sends multiple messages. I don't know how long they will be, but this is persistent per command, so you can specify it as arg
    */
   pub async fn read_nb_parcel(&mut self, frames: u8) -> Result<Vec<u8>> {
-    let mut buffer : Vec<u8> = Vec::new();
+    self.read_nb_parcel_timeout(frames, NB_NOTIFICATION_TIMEOUT).await
+  }
+
+  /**
+   * Same as read_nb_parcel, but every notification has to arrive in duration
+   */
+  pub async fn read_nb_parcel_timeout(&mut self, frames: u8, duration: Duration) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    self.read_nb_parcel_into(frames, duration, &mut buffer).await?;
+    Ok(buffer)
+  }
+
+  /**
+   * Same as read_nb_parcel_timeout, but parcel is assembled in allocation of out
+   */
+  pub async fn read_nb_parcel_into(&mut self, frames: u8, duration: Duration, out: &mut Vec<u8>) -> Result<()> {
+    let mut assembler = FrameAssembler::with_buffer(std::mem::take(out));
     let mut frames_left = frames;
-    let duration = Duration::from_secs(5);
 
     tracing::debug!("Reading nb frames: {}", frames_left);
-    while frames_left > 0 {
+    // Length from header wins over expected number of frames, so long responses are not truncated
+    while frames_left > 0 || assembler.expected_len().is_some() {
       tracing::debug!("  Reading frame...");
       let notification = self.wait_for_notification_with_timeout(duration).await?;
       tracing::debug!("  Received data: {:?}", notification.value.hex_dump());
-      buffer.extend_from_slice(notification.value.as_slice());
-      frames_left -= 1;
+      frames_left = frames_left.saturating_sub(1);
+
+      if let Some(frame) = assembler.push(&notification.value) {
+        tracing::debug!("  Finished reading: {:?}", frame.hex_dump());
+        *out = frame;
+        return Ok(())
+      }
     }
 
-    tracing::debug!("  Finished reading: {:?}", buffer.hex_dump());
-    Ok(buffer)
+    *out = assembler.take();
+    tracing::debug!("  Finished reading: {:?}", out.hex_dump());
+    Ok(())
   }
 
   /**
@@ -196,7 +371,7 @@ impl MiProtocol {
 
     for chunk in data.chunks(NB_CHUNK_SIZE) {
       tracing::debug!("Writing nb chunk to {:?}: {:?}", reg, chunk.hex_dump());
-      self.device.write(&channel, &chunk, WriteType::WithoutResponse).await
+      self.device.write(&channel, &chunk, self.write_type_for(channel)).await
         .with_context(|| format!("Could not write mi chunk: for channel: {:?}", channel))?;
     }
 
@@ -221,7 +396,7 @@ impl MiProtocol {
       }
 
       tracing::debug!("Writing mi chunk {} to {:?}: {:?}", chunk_index, reg, buffer.hex_dump());
-      self.device.write(&channel, &buffer, WriteType::WithoutResponse).await
+      self.device.write(&channel, &buffer, self.write_type_for(channel)).await
         .with_context(|| format!("Could not write mi chunk: {} for channel: {:?}", chunk_index, channel))?;
       chunk_index += 1;
     }
@@ -230,7 +405,19 @@ impl MiProtocol {
   }
 }
 
-async fn find_characteristic(device : &Peripheral, service_uuid: Uuid, char_uuid: Uuid) -> Result<Characteristic> {
+/**
+ * Discover services and look up characteristic, with skip_discovery cached characteristic is used when present
+ */
+async fn find_characteristic(device : &Peripheral, skip_discovery: bool, service_uuid: Uuid, char_uuid: Uuid) -> Result<Characteristic> {
+  if skip_discovery {
+    let cached = device.characteristics().into_iter()
+      .find(|ch| ch.uuid == char_uuid && ch.service_uuid == service_uuid);
+    if let Some(ch) = cached {
+      tracing::debug!("Found cached Characteristic: {:?}", ch);
+      return Ok(ch)
+    }
+  }
+
   device.discover_services().await
     .with_context(|| format!("Could not enable discovering devices"))?;
 
@@ -243,14 +430,17 @@ async fn find_characteristic(device : &Peripheral, service_uuid: Uuid, char_uuid
     }
   }
 
-  Err(anyhow!("Could not find characteristic: {}", char_uuid))
+  Err(SessionError::MissingCharacteristic {
+    expected: char_uuid,
+    found: device.characteristics().iter().map(|ch| ch.uuid).collect()
+  }.into())
 }
 
 fn what_frame(bytes: &Vec<u8>) -> u16 {
   bytes[0] as u16 & 0xff + 0x100 * bytes[1] as u16 & 0xff
 }
 
-async fn setup_channels(device : &Peripheral) -> Result<(Characteristic, Characteristic, Characteristic, Characteristic)> {
+async fn setup_channels(device : &Peripheral, skip_discovery: bool) -> Result<(Characteristic, Characteristic, Characteristic, Characteristic)> {
   let mut retries = 5;
   loop {
     // Windows BLE: verify connection is stable before discovering services
@@ -264,6 +454,11 @@ async fn setup_channels(device : &Peripheral) -> Result<(Characteristic, Charact
       continue;
     }
     
+    if skip_discovery && !device.characteristics().is_empty() {
+      tracing::debug!("Using cached characteristics, skipping discovery");
+      break;
+    }
+
     // Additional stabilization delay before service discovery on Windows
     #[cfg(target_os = "windows")]
     tokio::time::sleep(Duration::from_millis(500)).await;
@@ -296,13 +491,13 @@ async fn setup_channels(device : &Peripheral) -> Result<(Characteristic, Charact
 
   // Auth channels
   tracing::debug!("Setting up AUTH channels");
-  let avdtp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::AVDTP.to_uuid()).await?;
-  let upnp = find_characteristic(device, Registers::AUTH.to_uuid(), Registers::UPNP.to_uuid()).await?;
+  let avdtp = find_characteristic(device, skip_discovery, Registers::AUTH.to_uuid(), Registers::AVDTP.to_uuid()).await?;
+  let upnp = find_characteristic(device, skip_discovery, Registers::AUTH.to_uuid(), Registers::UPNP.to_uuid()).await?;
 
   // UART channels
   tracing::debug!("Setting up UART channels");
-  let tx = find_characteristic(device, Registers::UART.to_uuid(), Registers::TX.to_uuid()).await?;
-  let rx = find_characteristic(device, Registers::UART.to_uuid(), Registers::RX.to_uuid()).await?;
+  let tx = find_characteristic(device, skip_discovery, Registers::UART.to_uuid(), Registers::TX.to_uuid()).await?;
+  let rx = find_characteristic(device, skip_discovery, Registers::UART.to_uuid(), Registers::RX.to_uuid()).await?;
 
   tracing::debug!("Enabling notify for AVDTP");
   device.subscribe(&avdtp).await
@@ -0,0 +1,145 @@
+use crate::protocol_core::FrameAssembler;
+
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ReplayError {
+  #[error("Capture line {line}: {reason}")]
+  InvalidLine { line: usize, reason: String },
+  #[error("Capture expected request {expected:02X?}, but received {received:02X?}")]
+  UnexpectedRequest { expected: Vec<u8>, received: Vec<u8> },
+  #[error("Capture has no more requests, received {0:02X?}")]
+  Exhausted(Vec<u8>),
+  #[error("Could not read capture: {0}")]
+  Io(String),
+}
+
+/**
+ * One request written to scooter and notifications which came back before next request
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+  pub request: Vec<u8>,
+  pub notifications: Vec<Vec<u8>>,
+}
+
+impl Exchange {
+  /**
+   * Join notifications into frames the same way as MiProtocol and ScooterConnection do
+   */
+  pub fn frames(&self) -> Vec<Vec<u8>> {
+    let mut assembler = FrameAssembler::new();
+    let mut frames : Vec<Vec<u8>> = self.notifications.iter()
+      .filter_map(|notification| assembler.push(notification))
+      .collect();
+
+    let rest = assembler.take();
+    if !rest.is_empty() {
+      frames.push(rest);
+    }
+    frames
+  }
+}
+
+/**
+ * Captured BLE traffic of real scooter, to reproduce its behavior in tests without hardware. Text format,
+ * one packet per line, > is written request and < is notification received after it:
+ *
+ * ```text
+ * # M365 clone, firmware version
+ * > [55, AA, 03, 20, 01, 1A, 02, BF, FF]
+ * < [55, AA, 04, 23]
+ * < 01 1A 34 01 88 FF
+ * ```
+ *
+ * Bytes are accepted as printed by {:02X?} or as plain hex with or without spaces, blank lines and lines
+ * starting with # are skipped
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capture {
+  pub exchanges: Vec<Exchange>,
+}
+
+impl Capture {
+  pub fn parse(text: &str) -> Result<Self, ReplayError> {
+    let mut exchanges : Vec<Exchange> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue
+      }
+
+      let invalid = |reason: String| ReplayError::InvalidLine { line: number + 1, reason };
+      let direction = line.chars().next().unwrap_or_default();
+      let bytes = parse_bytes(&line[direction.len_utf8()..]).map_err(invalid)?;
+
+      match direction {
+        '>' => exchanges.push(Exchange { request: bytes, notifications: Vec::new() }),
+        '<' => exchanges.last_mut()
+          .ok_or_else(|| invalid("notification before first request".to_owned()))?
+          .notifications.push(bytes),
+        _ => return Err(invalid(format!("expected > or <, got {}", direction)))
+      }
+    }
+
+    Ok(Self { exchanges })
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+    let text = std::fs::read_to_string(path.as_ref())
+      .map_err(|err| ReplayError::Io(err.to_string()))?;
+    Self::parse(&text)
+  }
+
+  /**
+   * Answer requests in captured order, like scooter did
+   */
+  pub fn player(&self) -> Replay<'_> {
+    Replay { exchanges: self.exchanges.iter() }
+  }
+}
+
+/**
+ * Stand-in for scooter which answers with captured notifications, created by Capture::player
+ */
+pub struct Replay<'a> {
+  exchanges: std::slice::Iter<'a, Exchange>,
+}
+
+impl Replay<'_> {
+  /**
+   * Check that request is the next captured one and return frames scooter answered with
+   */
+  pub fn respond(&mut self, request: &[u8]) -> Result<Vec<Vec<u8>>, ReplayError> {
+    let exchange = self.exchanges.next()
+      .ok_or_else(|| ReplayError::Exhausted(request.to_vec()))?;
+
+    if exchange.request != request {
+      return Err(ReplayError::UnexpectedRequest { expected: exchange.request.clone(), received: request.to_vec() })
+    }
+    Ok(exchange.frames())
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.exchanges.len() == 0
+  }
+}
+
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+  let text = text.trim().trim_start_matches('[').trim_end_matches(']');
+  let tokens : Vec<&str> = text.split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|token| !token.is_empty())
+    .collect();
+
+  let digits : String = match tokens.as_slice() {
+    [] => return Err("no bytes".to_owned()),
+    tokens if tokens.iter().all(|token| token.trim_start_matches("0x").len() <= 2) => {
+      tokens.iter().map(|token| format!("{:0>2}", token.trim_start_matches("0x"))).collect()
+    },
+    tokens => tokens.concat()
+  };
+
+  hex::decode(&digits).map_err(|err| format!("invalid hex {}: {}", digits, err))
+}
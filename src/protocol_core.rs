@@ -0,0 +1,307 @@
+/*!
+ * Wire format shared by all connections: command encoding, checksums and frame reassembly.
+ * Uses only core and alloc, so it builds without default features (no_std) for firmware or WASM
+ */
+use alloc::vec::Vec;
+use alloc::vec;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Direction {
+  MasterToMotor,
+  MasterToBattery,
+  MotorToMaster,
+  BatteryToMaster,
+  /**
+   * Second battery pack of dual battery builds, only on extended firmwares
+   */
+  MasterToExtBattery,
+  ExtBatteryToMaster,
+}
+
+impl Direction {
+  fn value(&self) -> u8 {
+    match self {
+      Direction::MasterToMotor      => 0x20,
+      Direction::MasterToBattery    => 0x22,
+      Direction::MotorToMaster      => 0x23,
+      Direction::BatteryToMaster    => 0x25,
+      Direction::MasterToExtBattery => 0x24,
+      Direction::ExtBatteryToMaster => 0x26,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub enum ReadWrite {
+  Read,
+  Write
+}
+
+impl ReadWrite {
+  fn value(&self) -> u8 {
+    match self {
+      ReadWrite::Read     => 0x01,
+      ReadWrite::Write    => 0x03
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attribute {
+  GeneralInfo,
+  FirmwareVersion,
+  MotorInfo,
+  DistanceLeft,
+  Speed,
+  TripDistance,
+  TripInfo,
+  BatteryVoltage,
+  BatteryCurrent,
+  BatteryPercent,
+  BatteryCellVoltages,
+  Supplementary,
+  Cruise,
+  TailLight,
+  ZeroStart,
+  BatteryInfo,
+  ControllerTemp,
+  /**
+   * Write only, 0x0001 enables (0x70) or disables (0x71) anti-theft lock
+   */
+  Lock,
+  Unlock,
+  /**
+   * Any other register, for attributes which are not modeled by this library yet
+   */
+  Raw(u8)
+}
+
+impl Attribute {
+  pub(crate) fn value(&self) -> u8 {
+    match self {
+      Attribute::GeneralInfo          => 0x10,
+      Attribute::FirmwareVersion      => 0x1A,
+      Attribute::DistanceLeft         => 0x25,
+      Attribute::Speed                => 0xB5,
+      Attribute::TripDistance         => 0xB9,
+      Attribute::TripInfo             => 0x3A,
+      Attribute::BatteryVoltage       => 0x34,
+      Attribute::BatteryCurrent       => 0x33,
+      Attribute::BatteryPercent       => 0x32,
+      Attribute::MotorInfo            => 0xB0,
+      Attribute::BatteryCellVoltages  => 0x40,
+      Attribute::Supplementary        => 0x7B,
+      Attribute::Cruise               => 0x7C,
+      Attribute::TailLight            => 0x7D,
+      Attribute::ZeroStart            => 0x7E,
+      Attribute::BatteryInfo          => 0x31,
+      Attribute::ControllerTemp       => 0x3E,
+      Attribute::Lock                 => 0x70,
+      Attribute::Unlock               => 0x71,
+      Attribute::Raw(value)           => *value
+    }
+  }
+
+  /**
+   * Every readable attribute known by this library, without Raw
+   */
+  pub fn all() -> Vec<Attribute> {
+    vec![
+      Attribute::GeneralInfo,
+      Attribute::FirmwareVersion,
+      Attribute::MotorInfo,
+      Attribute::DistanceLeft,
+      Attribute::Speed,
+      Attribute::TripDistance,
+      Attribute::TripInfo,
+      Attribute::BatteryVoltage,
+      Attribute::BatteryCurrent,
+      Attribute::BatteryPercent,
+      Attribute::BatteryCellVoltages,
+      Attribute::Supplementary,
+      Attribute::Cruise,
+      Attribute::TailLight,
+      Attribute::ZeroStart,
+      Attribute::BatteryInfo,
+      Attribute::ControllerTemp,
+    ]
+  }
+
+  /**
+   * Controller which holds this attribute, battery values are read from BMS
+   */
+  pub fn direction(&self) -> Direction {
+    match self {
+      Attribute::BatteryVoltage |
+      Attribute::BatteryCurrent |
+      Attribute::BatteryPercent |
+      Attribute::BatteryCellVoltages |
+      Attribute::BatteryInfo => Direction::MasterToBattery,
+      _ => Direction::MasterToMotor
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct ScooterCommand {
+  pub direction: Direction,
+  pub read_write: ReadWrite,
+  pub attribute: Attribute,
+  pub payload: Vec<u8>
+}
+
+impl ScooterCommand {
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut bytes : Vec<u8> = Vec::new();
+    self.write_bytes(&mut bytes);
+    bytes
+  }
+
+  /**
+   * Same as as_bytes, but into out (cleared first), so one buffer can be reused for every command
+   */
+  pub fn write_bytes(&self, out: &mut Vec<u8>) {
+    out.clear();
+    out.push(self.payload.len() as u8 + 2u8);
+    out.push(self.direction.value());
+    out.push(self.read_write.value());
+    out.push(self.attribute.value());
+    out.extend_from_slice(&self.payload);
+  }
+}
+
+/**
+ * Checksum of Xiaomi UART frame: sum of bytes from length field to end of payload, xor 0xFFFF.
+ * Sent as little endian after payload, e.g. 03 20 01 1A 02 ends with BF FF
+ */
+pub fn xiaomi_checksum(data: &[u8]) -> u16 {
+  let sum: u32 = data.iter().map(|&byte| byte as u32).sum();
+  (sum as u16) ^ 0xFFFF
+}
+
+/**
+ * Checksum of Ninebot UART frame: 0xFFFF - sum of bytes from length field to end of payload, sent little endian.
+ * For 16 bit values this is equal to xiaomi_checksum, frames differ in header and layout instead
+ */
+pub fn ninebot_checksum(data: &[u8]) -> u16 {
+  let sum: u32 = data.iter().map(|&byte| byte as u32).sum();
+  0xFFFFu16.wrapping_sub(sum as u16)
+}
+
+/**
+ * Framing of plain UART messages used by clones:
+ * Xiaomi:  55 AA len dir rw attr payload ck ck, len is payload length + 2
+ * Ninebot: 5A A5 len src dst rw attr payload ck ck, len is payload length
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+  Xiaomi,
+  Ninebot,
+}
+
+impl Protocol {
+  pub fn header(&self) -> [u8; 2] {
+    match self {
+      Protocol::Xiaomi  => [0x55, 0xAA],
+      Protocol::Ninebot => [0x5A, 0xA5],
+    }
+  }
+
+  /**
+   * Checksum of frame without header and checksum bytes
+   */
+  pub fn checksum(&self, data: &[u8]) -> u16 {
+    match self {
+      Protocol::Xiaomi  => xiaomi_checksum(data),
+      Protocol::Ninebot => ninebot_checksum(data),
+    }
+  }
+
+  /**
+   * Bytes in frame which are not counted by its length field
+   */
+  fn frame_overhead(&self) -> usize {
+    match self {
+      Protocol::Xiaomi  => 6,
+      Protocol::Ninebot => 9,
+    }
+  }
+
+  /**
+   * Guess protocol from complete response frame: header, length and checksum have to match.
+   * Returns None for anything else, e.g. partial frame or encrypted one
+   */
+  pub fn detect(first_response: &[u8]) -> Option<Protocol> {
+    [Protocol::Xiaomi, Protocol::Ninebot].into_iter().find(|protocol| {
+      first_response.len() >= protocol.frame_overhead() &&
+        first_response[0..2] == protocol.header() &&
+        first_response.len() == first_response[2] as usize + protocol.frame_overhead() && {
+          let (body, checksum) = first_response[2..].split_at(first_response.len() - 4);
+          protocol.checksum(body) == u16::from_le_bytes([checksum[0], checksum[1]])
+        }
+    })
+  }
+}
+
+/**
+ * Join notifications into complete UART frames. Length is taken from frame header:
+ * 55 AB (encrypted) len + 16 bytes, 55 AA len + 6 bytes, 5A A5 len + 9 bytes.
+ * Bytes received after complete frame are kept for the next one
+ */
+#[derive(Debug, Default)]
+pub struct FrameAssembler {
+  buffer: Vec<u8>,
+}
+
+impl FrameAssembler {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * Assemble into existing allocation, buffer is cleared. Complete frame is returned in the same allocation
+   */
+  pub fn with_buffer(mut buffer: Vec<u8>) -> Self {
+    buffer.clear();
+    Self { buffer }
+  }
+
+  /**
+   * Add received chunk, returns frame when all its bytes arrived
+   */
+  pub fn push(&mut self, chunk: &[u8]) -> Option<Vec<u8>> {
+    self.buffer.extend_from_slice(chunk);
+
+    let expected = self.expected_len()?;
+    if self.buffer.len() < expected {
+      return None
+    }
+
+    let rest = self.buffer.split_off(expected);
+    Some(core::mem::replace(&mut self.buffer, rest))
+  }
+
+  /**
+   * Length of frame which is being assembled, None until header is received or when header is unknown
+   */
+  pub fn expected_len(&self) -> Option<usize> {
+    if self.buffer.len() < 3 {
+      return None
+    }
+
+    let length = self.buffer[2] as usize;
+    match [self.buffer[0], self.buffer[1]] {
+      [0x55, 0xAB] => Some(length + 16),
+      header if header == Protocol::Xiaomi.header() => Some(length + Protocol::Xiaomi.frame_overhead()),
+      header if header == Protocol::Ninebot.header() => Some(length + Protocol::Ninebot.frame_overhead()),
+      _ => None
+    }
+  }
+
+  /**
+   * Take whatever was received so far, e.g. frame with unknown header
+   */
+  pub fn take(&mut self) -> Vec<u8> {
+    core::mem::take(&mut self.buffer)
+  }
+}
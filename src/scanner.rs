@@ -2,13 +2,24 @@ use std::hash::Hash;
 use anyhow::Result;
 use tokio::sync::mpsc;
 use std::collections::HashSet;
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
+use tokio_stream::wrappers::ReceiverStream;
 use btleplug::platform::{Adapter, Manager, PeripheralId, Peripheral};
-use btleplug::api::{Central, Manager as _, ScanFilter, BDAddr, Peripheral as _, CentralEvent};
+use btleplug::api::{Central, Manager as _, ScanFilter, BDAddr, Peripheral as _, PeripheralProperties, CentralEvent};
 use thiserror::Error;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::AbortHandle;
 use tokio::sync::RwLock;
+use tokio::time::{self, Instant};
+use std::time::Duration;
 use uuid::Uuid;
+use crate::advertisement::PassiveTelemetry;
+use crate::connection::ConnectionHelper;
+use crate::login::LoginRequest;
+use crate::mi_crypto::AuthToken;
+use crate::session::MiSession;
+use crate::util::with_timeout;
 
 type Devices = Arc<RwLock<HashSet<TrackedDevice>>>;
 
@@ -17,13 +28,35 @@ type Devices = Arc<RwLock<HashSet<TrackedDevice>>>;
  */
 const XIAOMI_SCOOTER_NAME : &str = "MIScooter";
 const XIAOMI_SERVICE_UUID : &str = "0000fe95-0000-1000-8000-00805f9b34fb";
+/**
+ * Nordic UART service, advertised in scan response by M365 and by clones without FE95 service
+ */
+const NORDIC_UART_SERVICE_UUID : &str = "6e400001-b5a3-f393-e0a9-e50e24dcca9e";
+
+/**
+ * How long connect_known scans for scooter when adapter does not remember it
+ */
+const KNOWN_SCAN_TIMEOUT : Duration = Duration::from_secs(10);
+const KNOWN_SCAN_POLL_INTERVAL : Duration = Duration::from_millis(250);
+
+/**
+ * On cold boot bluetooth adapter may show up few seconds after us
+ */
+const ADAPTER_RETRY_ATTEMPTS : u32 = 5;
+const ADAPTER_RETRY_DELAY : Duration = Duration::from_secs(2);
+
+const EVENT_CHANNEL_CAPACITY : usize = 32;
 
 #[derive(Error, Debug)]
 pub enum ScannerError {
   #[error("Could not find scooter with addr: {0}")]
   WaitForScooterFailed(BDAddr),
+  #[error("Could not find scooter with name: {0}")]
+  WaitForNameFailed(String),
   #[error("Could not find working bluetooth adapter")]
   MissingCentral,
+  #[error("Bluetooth adapter unavailable: {}", if *powered_off { "bluetooth is turned off" } else { "permission denied" })]
+  AdapterUnavailable { powered_off: bool },
   #[error("Bluetooth error: {0}")]
   BluetoothError(btleplug::Error),
   #[error("Registration failed: {0}")]
@@ -42,6 +75,29 @@ impl From<btleplug::Error> for ScannerError {
   }
 }
 
+/**
+ * Messages reported when scanning with radio off: BlueZ "org.bluez.Error.NotReady", WinRT "device is not ready",
+ * CoreBluetooth "powered off" state
+ */
+const POWERED_OFF_MESSAGES : [&str; 3] = ["notready", "not ready", "powered off"];
+
+/**
+ * Map error of start_scan, telling apart disabled or forbidden adapter from other failures
+ */
+fn scan_error(err: btleplug::Error) -> ScannerError {
+  match err {
+    btleplug::Error::PermissionDenied => ScannerError::AdapterUnavailable { powered_off: false },
+    err => {
+      let message = err.to_string().to_lowercase();
+      if POWERED_OFF_MESSAGES.iter().any(|pattern| message.contains(pattern)) {
+        ScannerError::AdapterUnavailable { powered_off: true }
+      } else {
+        ScannerError::BluetoothError(err)
+      }
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub enum ScannerEvent {
   DiscoveredScooter(TrackedDevice)
@@ -85,28 +141,276 @@ impl PartialEq for TrackedDevice {
 #[derive(Clone)]
 pub struct ScooterScanner {
   devices: Devices,
+  scan_filter: ScanFilter,
+  scan_guard: Arc<ScanGuard>,
+  channel_capacity: usize,
+  stop_on_found: bool,
+  settle_window: Duration,
   pub central: Adapter,
 }
 
+/**
+ * Shared by all clones of scanner. When last clone is dropped, background tasks are aborted and stop_scan is
+ * spawned on current tokio runtime (Drop can't await, so it is not guaranteed to finish before process exits,
+ * call ScooterScanner::close for that)
+ */
+struct ScanGuard {
+  central: Adapter,
+  scanning: AtomicBool,
+  tasks: std::sync::Mutex<Vec<AbortHandle>>,
+}
+
+impl ScanGuard {
+  fn abort_tasks(&self) {
+    for task in self.tasks.lock().unwrap().drain(..) {
+      task.abort();
+    }
+  }
+}
+
+impl Drop for ScanGuard {
+  fn drop(&mut self) {
+    self.abort_tasks();
+
+    if !self.scanning.load(Ordering::SeqCst) {
+      return
+    }
+
+    match tokio::runtime::Handle::try_current() {
+      Ok(runtime) => {
+        let central = self.central.clone();
+        runtime.spawn(async move {
+          if let Err(err) = central.stop_scan().await {
+            tracing::warn!("Could not stop scanning: {}", err);
+          }
+        });
+      },
+      Err(_) => tracing::warn!("Scanner dropped outside of tokio runtime, scan is not stopped")
+    }
+  }
+}
+
+/**
+ * All options of ScooterScanner in one place, created by ScooterScanner::builder. Defaults are the same as
+ * ScooterScanner::new: first adapter, Xiaomi and Nordic UART scan filter, 5 attempts to find adapter.
+ *
+ * ```no_run
+ * # async fn run() -> Result<(), ninebot_ble::scanner::ScannerError> {
+ * use btleplug::api::ScanFilter;
+ * use ninebot_ble::ScooterScanner;
+ * use uuid::Uuid;
+ *
+ * // second adapter (e.g. USB dongle), only devices advertising Xiaomi service
+ * let scanner = ScooterScanner::builder()
+ *   .adapter_index(1)
+ *   .scan_filter(ScanFilter { services: vec![Uuid::from_u128(0x0000fe95_0000_1000_8000_00805f9b34fb)] })
+ *   .channel_capacity(8)
+ *   .build()
+ *   .await?;
+ * # Ok(())
+ * # }
+ * ```
+ */
+pub struct ScooterScannerBuilder {
+  adapter: Option<Adapter>,
+  adapter_index: usize,
+  attempts: u32,
+  delay: Duration,
+  scan_filter: ScanFilter,
+  channel_capacity: usize,
+  stop_on_found: bool,
+  settle_window: Duration,
+}
+
+impl Default for ScooterScannerBuilder {
+  fn default() -> Self {
+    Self {
+      adapter: None,
+      adapter_index: 0,
+      attempts: ADAPTER_RETRY_ATTEMPTS,
+      delay: ADAPTER_RETRY_DELAY,
+      scan_filter: default_scan_filter(),
+      channel_capacity: EVENT_CHANNEL_CAPACITY,
+      stop_on_found: true,
+      settle_window: Duration::ZERO,
+    }
+  }
+}
+
+impl ScooterScannerBuilder {
+  /**
+   * Use this adapter, adapter_index and retries are ignored then
+   */
+  pub fn adapter(mut self, adapter: Adapter) -> Self {
+    self.adapter = Some(adapter);
+    self
+  }
+
+  /**
+   * Position of adapter in list reported by platform, 0 is the first one
+   */
+  pub fn adapter_index(mut self, index: usize) -> Self {
+    self.adapter_index = index;
+    self
+  }
+
+  /**
+   * How many times to look for adapter and how long to wait between attempts, see ScooterScanner::with_adapter_retries
+   */
+  pub fn adapter_retries(mut self, attempts: u32, delay: Duration) -> Self {
+    self.attempts = attempts;
+    self.delay = delay;
+    self
+  }
+
+  /**
+   * See ScooterScanner::with_scan_filter
+   */
+  pub fn scan_filter(mut self, scan_filter: ScanFilter) -> Self {
+    self.scan_filter = scan_filter;
+    self
+  }
+
+  /**
+   * See ScooterScanner::scan_all
+   */
+  pub fn scan_all(self) -> Self {
+    self.scan_filter(ScanFilter::default())
+  }
+
+  /**
+   * See ScooterScanner::with_channel_capacity
+   */
+  pub fn channel_capacity(mut self, capacity: usize) -> Self {
+    self.channel_capacity = capacity.max(1);
+    self
+  }
+
+  /**
+   * See ScooterScanner::with_stop_on_found
+   */
+  pub fn stop_on_found(mut self, stop: bool) -> Self {
+    self.stop_on_found = stop;
+    self
+  }
+
+  /**
+   * See ScooterScanner::with_settle_window
+   */
+  pub fn settle_window(mut self, window: Duration) -> Self {
+    self.settle_window = window;
+    self
+  }
+
+  pub async fn build(self) -> Result<ScooterScanner, ScannerError> {
+    let central = match self.adapter {
+      Some(adapter) => adapter,
+      None => wait_for_central(self.adapter_index, self.attempts, self.delay).await?
+    };
+    let devices  = Arc::new(RwLock::new(HashSet::new()));
+    let scan_guard = Arc::new(ScanGuard {
+      central: central.clone(),
+      scanning: AtomicBool::new(false),
+      tasks: std::sync::Mutex::new(Vec::new()),
+    });
+
+    Ok(ScooterScanner {
+      central,
+      devices,
+      scan_filter: self.scan_filter,
+      scan_guard,
+      channel_capacity: self.channel_capacity,
+      stop_on_found: self.stop_on_found,
+      settle_window: self.settle_window,
+    })
+  }
+}
+
 impl ScooterScanner {
   pub async fn new() -> Result<Self, ScannerError> {
-    let manager  = Manager::new().await?;
-    let central  = find_central(&manager).await?;
-    let devices  = Arc::new(RwLock::new(HashSet::new()));
+    Self::builder().build().await
+  }
+
+  pub fn builder() -> ScooterScannerBuilder {
+    ScooterScannerBuilder::default()
+  }
+
+  /**
+   * Create scanner and wait for bluetooth adapter to become available. Useful for services started on boot,
+   * before bluetooth daemon is ready. Returns ScannerError::MissingCentral when all attempts failed
+   */
+  pub async fn with_adapter_retries(attempts: u32, delay: Duration) -> Result<Self, ScannerError> {
+    Self::builder().adapter_retries(attempts, delay).build().await
+  }
 
-    Ok(Self { central, devices })
+  /**
+   * Replace scan filter. By default only devices advertising Xiaomi (FE95) or Nordic UART service are reported
+   */
+  pub fn with_scan_filter(mut self, scan_filter: ScanFilter) -> Self {
+    self.scan_filter = scan_filter;
+    self
+  }
+
+  /**
+   * Size of channel returned by start. Events are never awaited by BLE event processing, when consumer is slow
+   * and channel is full new DiscoveredScooter events are dropped (and logged) instead of stalling discovery.
+   * scooters_stream is the exception, it waits for its consumer
+   */
+  pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+    self.channel_capacity = capacity.max(1);
+    self
+  }
+
+  /**
+   * Stop scanning as soon as wait_for or wait_for_name is done, so adapter is idle before connecting. On by default,
+   * turn it off when other consumers of start still need discovery events
+   */
+  pub fn with_stop_on_found(mut self, stop: bool) -> Self {
+    self.stop_on_found = stop;
+    self
   }
 
   /**
-   * Wait for scooter with mac address to appear and return it.
+   * Before wait_for starts scanning, poll devices already cached by adapter for this long (BlueZ, WinRT and
+   * CoreBluetooth report them without scan). When scooter is among them no scan is started at all.
+   * Adapters keep devices cached long after they went out of range or were switched off, so cache is consulted
+   * only with non-zero window. Default is Duration::ZERO, which always confirms scooter with scan
    */
+  pub fn with_settle_window(mut self, window: Duration) -> Self {
+    self.settle_window = window;
+    self
+  }
+
+  /**
+   * Scan every device without filtering by service. Some platforms hide devices which don't advertise
+   * filtered service in primary advertisement packet, use this when your scooter does not show up
+   */
+  pub fn scan_all(self) -> Self {
+    self.with_scan_filter(ScanFilter::default())
+  }
+
+  /**
+   * Wait for scooter with mac address to appear and return it. Scooters already found by this scanner and
+   * devices cached by adapter (see with_settle_window) are checked first, then scan is started and stopped
+   * again once scooter shows up (see with_stop_on_found).
+   */
+  #[tracing::instrument(skip(self), fields(addr = %scooter_with_address))]
   pub async fn wait_for(&mut self, scooter_with_address: &BDAddr) -> Result<TrackedDevice, ScannerError> {
-    let mut rx = self.start().await?;
+    if let Some(scooter) = self.cached_scooter(scooter_with_address).await? {
+      tracing::info!("Already found your scooter");
+      return Ok(scooter)
+    }
+
+    let mut rx = self.start().await
+      .map_err(|err| err.downcast::<ScannerError>().unwrap_or_else(ScannerError::Other))?;
     while let Some(event) = rx.recv().await {
       match event {
         ScannerEvent::DiscoveredScooter(scooter) => {
           if scooter.addr == *scooter_with_address {
             tracing::info!("Found your scooter");
+            if self.stop_on_found {
+              self.stop_scan().await;
+            }
             return Ok(scooter)
           } else {
             tracing::info!("Found scooter nearby: {} with mac: {}", scooter.name.unwrap(), scooter.addr);
@@ -118,6 +422,69 @@ impl ScooterScanner {
     Err(ScannerError::WaitForScooterFailed(*scooter_with_address))
   }
 
+  /**
+   * Scooter found by earlier scan or cached by adapter, polled until settle_window passes.
+   * Adapter cache is skipped when settle_window is zero
+   */
+  async fn cached_scooter(&self, addr: &BDAddr) -> Result<Option<TrackedDevice>, ScannerError> {
+    if let Some(scooter) = self.scooters().await.into_iter().find(|scooter| scooter.addr == *addr) {
+      return Ok(Some(scooter))
+    }
+
+    if self.settle_window.is_zero() {
+      return Ok(None)
+    }
+
+    let settled = Instant::now() + self.settle_window;
+    loop {
+      if let Some(peripheral) = self.known_peripheral(addr).await? {
+        let props = peripheral.properties().await?;
+        return Ok(Some(tracked_device(&peripheral, props.as_ref())))
+      }
+      if Instant::now() >= settled {
+        return Ok(None)
+      }
+      time::sleep(KNOWN_SCAN_POLL_INTERVAL.min(settled - Instant::now())).await;
+    }
+  }
+
+  /**
+   * Wait for scooter whose advertised name is name or starts with it, e.g. renamed "My Scooter".
+   * Only devices passing scooter check are matched (FE95 service or MIScooter name), so clones renamed
+   * without FE95 service won't be found this way. Scan is stopped when it ends, found or not (see with_stop_on_found)
+   */
+  #[tracing::instrument(skip(self, timeout))]
+  pub async fn wait_for_name(&mut self, name: &str, timeout: Duration) -> Result<TrackedDevice, ScannerError> {
+    let matches = |scooter: &TrackedDevice| scooter.name.as_deref().is_some_and(|scooter_name| scooter_name.starts_with(name));
+
+    if let Some(scooter) = self.scooters().await.into_iter().find(matches) {
+      tracing::info!("Already found your scooter: {}", scooter.addr);
+      return Ok(scooter)
+    }
+
+    let mut rx = self.start().await
+      .map_err(|err| err.downcast::<ScannerError>().unwrap_or_else(ScannerError::Other))?;
+    let found = with_timeout(async {
+      while let Some(ScannerEvent::DiscoveredScooter(scooter)) = rx.recv().await {
+        if matches(&scooter) {
+          tracing::info!("Found your scooter with mac: {}", scooter.addr);
+          return Some(scooter)
+        }
+        tracing::info!("Found scooter nearby: {:?} with mac: {}", scooter.name, scooter.addr);
+      }
+      None
+    }, timeout).await;
+
+    if self.stop_on_found {
+      self.stop_scan().await;
+    }
+
+    match found {
+      Ok(Some(scooter)) => Ok(scooter),
+      _ => Err(ScannerError::WaitForNameFailed(name.to_owned()))
+    }
+  }
+
   /**
    * Get bluetooth Peripheral/Device using TrackedDevice struct
    */
@@ -125,28 +492,179 @@ impl ScooterScanner {
     Ok(self.central.peripheral(&tracked_device.id).await?)
   }
 
+  /**
+   * Get peripheral for scooter which address you already know, e.g. passed from command line.
+   * When bluetooth adapter still remembers the scooter (BlueZ and WinRT keep devices seen in previous runs)
+   * peripheral is returned right away without scanning. Otherwise short targeted scan is started and scooter
+   * must show up in KNOWN_SCAN_TIMEOUT.
+   */
+  pub async fn connect_known(&self, addr: &BDAddr) -> Result<Peripheral, ScannerError> {
+    if let Some(peripheral) = self.known_peripheral(addr).await? {
+      tracing::debug!("Adapter already knows scooter: {}", addr);
+      return Ok(peripheral)
+    }
+
+    tracing::debug!("Scooter {} is not known by adapter, scanning...", addr);
+    self.central.start_scan(self.scan_filter.clone()).await.map_err(scan_error)?;
+
+    let found = self.wait_for_peripheral(addr).await;
+    self.stop_scan().await;
+
+    found
+  }
+
+  /**
+   * Scan, connect and login without stopping scan in between. Some platforms invalidate peripheral handle
+   * once scan stops, so scan keeps running until connection is established
+   */
+  pub async fn find_and_connect(&mut self, addr: &BDAddr, token: &AuthToken) -> Result<MiSession> {
+    self.central.start_scan(self.scan_filter.clone()).await.map_err(scan_error)?;
+
+    let connected = async {
+      let device = self.wait_for_peripheral(addr).await?;
+      let connection = ConnectionHelper::with_adapter(&device, &self.central);
+      connection.connect().await?;
+      Ok::<Peripheral, anyhow::Error>(connection.device())
+    }.await;
+    self.stop_scan().await;
+
+    let device = connected?;
+    tracing::debug!("Connected to {}, logging in", addr);
+    LoginRequest::new(&device, token).await?.start().await
+  }
+
+  /**
+   * Poll adapter until scooter shows up, scan must be already running
+   */
+  async fn wait_for_peripheral(&self, addr: &BDAddr) -> Result<Peripheral, ScannerError> {
+    let found = with_timeout(async {
+      loop {
+        if let Some(peripheral) = self.known_peripheral(addr).await? {
+          return Ok::<Peripheral, ScannerError>(peripheral)
+        }
+        time::sleep(KNOWN_SCAN_POLL_INTERVAL).await;
+      }
+    }, KNOWN_SCAN_TIMEOUT).await;
+
+    match found {
+      Ok(peripheral) => peripheral,
+      Err(_) => Err(ScannerError::WaitForScooterFailed(*addr))
+    }
+  }
+
+  async fn stop_scan(&self) {
+    if let Err(err) = self.central.stop_scan().await {
+      tracing::warn!("Could not stop scanning: {}", err);
+    }
+    self.scan_guard.scanning.store(false, Ordering::SeqCst);
+  }
+
+  /**
+   * Stop scanning and background event processing started by start. Dropping last clone of scanner
+   * does the same, but without waiting for adapter to confirm
+   */
+  pub async fn close(&self) {
+    tracing::debug!("Closing scanner");
+    self.scan_guard.abort_tasks();
+    self.stop_scan().await;
+  }
+
+  /**
+   * Read telemetry from latest advertisement of device, no connection needed. Only some clones broadcast
+   * battery level, None is returned for devices which don't (or were not seen by adapter yet)
+   */
+  pub async fn passive_data(&self, addr: &BDAddr) -> Option<PassiveTelemetry> {
+    let peripheral = match self.known_peripheral(addr).await {
+      Ok(peripheral) => peripheral?,
+      Err(err) => {
+        tracing::debug!("Could not get peripheral {}: {}", addr, err);
+        return None
+      }
+    };
+
+    let props = peripheral.properties().await.ok()??;
+    PassiveTelemetry::from_advertisement(&props.service_data, &props.manufacturer_data)
+  }
+
+  async fn known_peripheral(&self, addr: &BDAddr) -> Result<Option<Peripheral>, ScannerError> {
+    let peripherals = self.central.peripherals().await?;
+    Ok(peripherals.into_iter().find(|peripheral| peripheral.address() == *addr))
+  }
+
+  /**
+   * Scooters which adapter already knows about without scanning, e.g. bonded or cached by OS (persists on
+   * Windows and macOS, BlueZ keeps paired devices). Connect to them with ScooterScanner::peripheral right away.
+   * Empty when platform does not expose such list
+   */
+  pub async fn known_peripherals(&self) -> Result<Vec<TrackedDevice>, ScannerError> {
+    let peripherals = match self.central.peripherals().await {
+      Ok(peripherals) => peripherals,
+      Err(btleplug::Error::NotSupported(err)) => {
+        tracing::debug!("Listing known peripherals is not supported: {}", err);
+        return Ok(vec![])
+      },
+      Err(err) => return Err(err.into())
+    };
+
+    let mut scooters = vec![];
+    for peripheral in peripherals {
+      let props = peripheral.properties().await?;
+      let tracked_device = tracked_device(&peripheral, props.as_ref());
+      if tracked_device.is_scooter() {
+        scooters.push(tracked_device);
+      }
+    }
+
+    tracing::debug!("Adapter knows {} scooter(s)", scooters.len());
+    Ok(scooters)
+  }
+
   /**
    * Start scanning for scooters. This method returns receiver which emits
-   * events every time a scooter is visible by bluetooth adapter
+   * events every time a scooter is visible by bluetooth adapter.
+   * Events are dropped when receiver falls behind by more than channel capacity (see with_channel_capacity),
+   * dropped scooters are still listed by scooters()
    */
   pub async fn start(&mut self) -> Result<mpsc::Receiver<ScannerEvent>> {
-    let (tx, rx) = mpsc::channel::<ScannerEvent>(32);
+    self.start_events(false).await
+  }
+
+  /**
+   * Start scanning, lossless processor waits for consumer when channel is full instead of dropping events
+   */
+  async fn start_events(&mut self, lossless: bool) -> Result<mpsc::Receiver<ScannerEvent>> {
+    let (tx, rx) = mpsc::channel::<ScannerEvent>(self.channel_capacity);
     tracing::debug!("Starting scanning for new devices");
-    self.central.start_scan(ScanFilter::default()).await?;
+    self.central.start_scan(self.scan_filter.clone()).await.map_err(scan_error)?;
+    self.scan_guard.scanning.store(true, Ordering::SeqCst);
 
     tracing::debug!("Watching for events in background");
     let central = self.central.clone();
     let devices = self.devices.clone();
 
-    tokio::spawn(async move {
-      if let Err(e) = CentralEventsProcessor::new(tx, central, devices).run().await {
+    let task = tokio::spawn(async move {
+      if let Err(e) = CentralEventsProcessor::new(tx, central, devices, lossless).run().await {
         tracing::error!("Stopped processed events {}", e);
       }
     });
+    self.scan_guard.tasks.lock().unwrap().push(task.abort_handle());
 
     Ok(rx)
   }
 
+  /**
+   * Start scanning and yield every newly discovered scooter once, e.g. to fill list in UI. Stream ends when
+   * scanning is stopped by close or when last clone of scanner is dropped. Combine with StreamExt::take or
+   * tokio::time::timeout to stop earlier. Scooters found before this call are in scooters.
+   * Unlike start, no scooter is dropped when consumer is slow, discovery waits until stream is polled
+   */
+  pub async fn scooters_stream(&mut self) -> Result<impl Stream<Item = TrackedDevice>, ScannerError> {
+    let rx = self.start_events(true).await
+      .map_err(|err| err.downcast::<ScannerError>().unwrap_or_else(ScannerError::Other))?;
+
+    Ok(ReceiverStream::new(rx).map(|ScannerEvent::DiscoveredScooter(scooter)| scooter))
+  }
+
   /**
    * Get list of scooters nearby you
    */
@@ -176,15 +694,20 @@ impl ScooterScanner {
 struct CentralEventsProcessor {
   central: Adapter,
   tx: mpsc::Sender<ScannerEvent>,
-  devices: Devices
+  devices: Devices,
+  /**
+   * Wait for free slot in channel instead of dropping event, devices are reported only once
+   */
+  lossless: bool
 }
 
 impl CentralEventsProcessor {
-  pub fn new(tx: mpsc::Sender<ScannerEvent>, central: Adapter, devices: Devices) -> Self {
+  pub fn new(tx: mpsc::Sender<ScannerEvent>, central: Adapter, devices: Devices, lossless: bool) -> Self {
     Self {
       central,
       tx,
-      devices
+      devices,
+      lossless
     }
   }
 
@@ -195,8 +718,13 @@ impl CentralEventsProcessor {
       match event {
         CentralEvent::DeviceDiscovered(peer_id) => {
           if let Some(tracked_device) = self.track_device(&peer_id).await? {
-            if tracked_device.is_scooter() {
+            if tracked_device.is_scooter() && self.lossless {
               self.tx.send(ScannerEvent::DiscoveredScooter(tracked_device)).await?;
+            } else if tracked_device.is_scooter() {
+              match self.tx.try_send(ScannerEvent::DiscoveredScooter(tracked_device)) {
+                Err(mpsc::error::TrySendError::Full(event)) => tracing::warn!("Scanner events are not consumed, dropped: {:?}", event),
+                result => result?
+              }
             }
           }
         },
@@ -209,31 +737,19 @@ impl CentralEventsProcessor {
   async fn track_device(&mut self, peer_id: &PeripheralId) -> Result<Option<TrackedDevice>> {
     tracing::debug!("Discovered peer: {:?}", peer_id);
     let device = self.central.peripheral(peer_id).await?;
-
-    let mut tracked_device = TrackedDevice {
-      id: peer_id.clone(),
-      addr: device.address(),
-      name: None,
-      has_xiaomi_service: false,
-    };
+    let addr = device.address();
 
     let mut devices = self.devices.write().await;
 
-    if devices.contains(&tracked_device) {
-      tracing::debug!("Already discovered: {}", tracked_device.addr);
+    if devices.iter().any(|tracked_device| tracked_device.addr == addr) {
+      tracing::debug!("Already discovered: {}", addr);
       Ok(None)
     } else {
       let props = device.properties().await?.unwrap();
       tracing::debug!("Props: {:?}", props);
 
-      let name = props.local_name.unwrap_or("(peripheral name unknown)".to_owned());
-      tracing::debug!("Device name: {}", name);
-      tracked_device.name = Some(name);
-
-      let xiaomi_uuid = Uuid::parse_str(XIAOMI_SERVICE_UUID).unwrap();
-      if props.service_data.contains_key(&xiaomi_uuid) || props.services.contains(&xiaomi_uuid) {
-        tracked_device.has_xiaomi_service = true;
-      }
+      let tracked_device = tracked_device(&device, Some(&props));
+      tracing::debug!("Device name: {}", tracked_device.name.as_deref().unwrap_or_default());
 
       devices.insert(tracked_device.clone());
       Ok(Some(tracked_device))
@@ -241,10 +757,101 @@ impl CentralEventsProcessor {
   }
 }
 
-async fn find_central(manager: &Manager) -> Result<Adapter, ScannerError> {
+/**
+ * Build TrackedDevice from peripheral and its advertised properties
+ */
+fn tracked_device(peripheral: &Peripheral, props: Option<&PeripheralProperties>) -> TrackedDevice {
+  let xiaomi_uuid = Uuid::parse_str(XIAOMI_SERVICE_UUID).unwrap();
+
+  TrackedDevice {
+    id: peripheral.id(),
+    addr: peripheral.address(),
+    name: Some(props.and_then(|props| props.local_name.clone()).unwrap_or("(peripheral name unknown)".to_owned())),
+    has_xiaomi_service: props.is_some_and(|props| {
+      props.service_data.contains_key(&xiaomi_uuid) || props.services.contains(&xiaomi_uuid)
+    }),
+  }
+}
+
+/**
+ * Parse bluetooth address written as AA:BB:CC:DD:EE:FF, AA-BB-CC-DD-EE-FF or AABBCCDDEEFF
+ */
+pub fn parse_mac(addr: &str) -> Result<BDAddr> {
+  let digits : String = addr.trim()
+    .chars()
+    .filter(|c| !matches!(c, ':' | '-'))
+    .collect();
+
+  if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(anyhow::anyhow!("Invalid MAC address: {}", addr))
+  }
+
+  BDAddr::from_str_no_delim(&digits)
+    .map_err(|err| anyhow::anyhow!("Invalid MAC address: {}: {}", addr, err))
+}
+
+fn default_scan_filter() -> ScanFilter {
+  ScanFilter {
+    services: vec![
+      Uuid::parse_str(XIAOMI_SERVICE_UUID).unwrap(),
+      Uuid::parse_str(NORDIC_UART_SERVICE_UUID).unwrap(),
+    ]
+  }
+}
+
+/**
+ * Leave adapter in clean state after connect was cancelled: stop scanning and disconnect scooter
+ */
+pub(crate) async fn release_connect(addr: &BDAddr) {
+  let central = match wait_for_central(0, 1, Duration::ZERO).await {
+    Ok(central) => central,
+    Err(_) => return
+  };
+
+  if let Err(err) = central.stop_scan().await {
+    tracing::debug!("Could not stop scanning: {}", err);
+  }
+
+  let peripherals = central.peripherals().await.unwrap_or_default();
+  for peripheral in peripherals.into_iter().filter(|peripheral| peripheral.address() == *addr) {
+    if peripheral.is_connected().await.unwrap_or(false) {
+      if let Err(err) = peripheral.disconnect().await {
+        tracing::debug!("Could not disconnect {}: {}", addr, err);
+      }
+    }
+  }
+}
+
+async fn wait_for_central(index: usize, attempts: u32, delay: Duration) -> Result<Adapter, ScannerError> {
+  let attempts = attempts.max(1);
+
+  for attempt in 1..=attempts {
+    let central = match Manager::new().await {
+      Ok(manager) => find_central_at(&manager, index).await,
+      Err(err) => Err(err.into())
+    };
+
+    match central {
+      Ok(central) => return Ok(central),
+      Err(err) => tracing::warn!("Bluetooth adapter is not ready ({}/{}): {}", attempt, attempts, err)
+    }
+
+    if attempt < attempts {
+      time::sleep(delay).await;
+    }
+  }
+
+  Err(ScannerError::MissingCentral)
+}
+
+pub(crate) async fn find_central(manager: &Manager) -> Result<Adapter, ScannerError> {
+  find_central_at(manager, 0).await
+}
+
+async fn find_central_at(manager: &Manager, index: usize) -> Result<Adapter, ScannerError> {
   let adapters = manager.adapters().await?;
 
-  if let Some(adapter) = adapters.into_iter().nth(0) {
+  if let Some(adapter) = adapters.into_iter().nth(index) {
     Ok(adapter)
   } else {
     Err(ScannerError::MissingCentral)
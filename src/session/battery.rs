@@ -1,11 +1,41 @@
 use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use super::response::{Response, expect_attribute};
+use super::info::StatusFlags;
+use crate::util::with_timeout;
 
-use anyhow::Result;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
 use serde::Serialize;
+use futures::Stream;
+use futures::stream;
 
 pub type BatteryCellsVoltage = [f32; 10];
 
+/**
+ * How long to wait for external pack before deciding it is not installed
+ */
+const EXTERNAL_PACK_TIMEOUT : Duration = Duration::from_millis(1500);
+
+/**
+ * Battery pack of dual battery builds. External pack answers on its own direction (0x24) and
+ * only when firmware supports second BMS
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BatteryPack {
+  Internal,
+  External,
+}
+
+impl BatteryPack {
+  fn direction(&self) -> Direction {
+    match self {
+      BatteryPack::Internal => Direction::MasterToBattery,
+      BatteryPack::External => Direction::MasterToExtBattery,
+    }
+  }
+}
+
 #[derive(Debug, Serialize)]
 pub struct BatteryInfo {
   /**
@@ -22,8 +52,35 @@ pub struct BatteryInfo {
    * Current measured voltage for all batteries, in Volts
    */
   pub voltage: f32,
-  pub temperature_1: u8,
-  pub temperature_2: u8,
+  /**
+   * Temperature in celsius, below zero in cold weather
+   */
+  pub temperature_1: f32,
+  pub temperature_2: f32,
+}
+
+/**
+ * BMS sends every temperature sensor as one unsigned byte of celsius + 20, so -20°C to 235°C fit in it
+ */
+const BMS_TEMPERATURE_OFFSET : f32 = 20.0;
+
+fn bms_temperature(raw: u8) -> f32 {
+  raw as f32 - BMS_TEMPERATURE_OFFSET
+}
+
+/**
+ * Charger can stay plugged in after pack is full, so charger_present does not mean battery is charging
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChargeState {
+  /**
+   * Battery takes current from charger
+   */
+  pub charging: bool,
+  /**
+   * Controller reports charger plugged into charging port
+   */
+  pub charger_present: bool,
 }
 
 impl TryFrom<Payload> for BatteryInfo {
@@ -39,13 +96,28 @@ impl TryFrom<Payload> for BatteryInfo {
         percent: payload.pop_u16()?,
         current: payload.pop_i16()? as f32 / 100.0,
         voltage: payload.pop_u16()? as f32 / 100.0,
-        temperature_1: payload.pad_byte()?,
-        temperature_2: payload.pad_byte()?,
+        temperature_1: bms_temperature(payload.pad_byte()?),
+        temperature_2: bms_temperature(payload.pad_byte()?),
       }
     )
   }
 }
 
+impl Response for BatteryInfo {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    BatteryInfo::try_from(expect_attribute(&attribute, Attribute::BatteryInfo, payload)?)
+  }
+}
+
+fn battery_info_command(pack: BatteryPack) -> ScooterCommand {
+  ScooterCommand {
+    direction: pack.direction(),
+    read_write: ReadWrite::Read,
+    attribute: Attribute::BatteryInfo,
+    payload: vec![0x0A]
+  }
+}
+
 impl MiSession {
   /**
    * Battery voltage in volts
@@ -110,6 +182,20 @@ impl MiSession {
     Ok(percent)
   }
 
+  /**
+   * Read battery current (0x33) every interval. Current is signed, so it is negative while regenerative
+   * braking charges the battery. Stream never ends by itself, failed reads are yielded as errors
+   */
+  pub fn current_stream(&mut self, interval: Duration) -> impl Stream<Item = Result<f32>> + '_ {
+    let ticker = tokio::time::interval(interval);
+
+    stream::unfold((self, ticker), |(session, mut ticker)| async move {
+      ticker.tick().await;
+      let current = session.battery_amperage().await;
+      Some((current, (session, ticker)))
+    })
+  }
+
   pub async fn battery_cell_voltages(&mut self) -> Result<BatteryCellsVoltage> {
     tracing::debug!("Reading battery cell voltages");
 
@@ -139,18 +225,61 @@ impl MiSession {
     Ok(voltages)
   }
 
+  /**
+   * Read charger state. Charger presence comes from CHARGING status flag (0xB0 block), charging
+   * additionally requires negative battery current (0x33), which is current flowing into pack
+   */
+  pub async fn charge_state(&mut self) -> Result<ChargeState> {
+    tracing::debug!("Reading charge state");
+
+    let charger_present = self.status_flags().await?.contains(StatusFlags::CHARGING);
+    let charging = charger_present && self.battery_amperage().await? < 0.0;
+
+    Ok(ChargeState { charging, charger_present })
+  }
+
+  pub async fn is_charging(&mut self) -> Result<bool> {
+    Ok(self.charge_state().await?.charging)
+  }
+
+  /**
+   * Battery info of internal pack
+   */
   pub async fn battery_info(&mut self) -> Result<BatteryInfo> {
-    self.send(&ScooterCommand {
-      direction: Direction::MasterToBattery,
-      read_write: ReadWrite::Read,
-      attribute: Attribute::BatteryInfo,
-      payload: vec![0x0A]
-    }).await?;
+    self.battery_info_for(BatteryPack::Internal).await
+  }
 
-    let payload = self.read(2).await?;
+  /**
+   * Battery info of internal pack with decrypted response it was decoded from, for diagnostics of wrong readings
+   */
+  pub async fn battery_info_raw(&mut self) -> Result<(BatteryInfo, Vec<u8>)> {
+    tracing::debug!("Reading battery info with raw response");
 
-    Ok(
-      BatteryInfo::try_from(payload)?
-    )
+    self.request_raw(&battery_info_command(BatteryPack::Internal), 2).await
+  }
+
+  /**
+   * Battery info of selected pack. Fails with "No external battery" when external pack does not respond
+   */
+  pub async fn battery_info_for(&mut self, pack: BatteryPack) -> Result<BatteryInfo> {
+    tracing::debug!("Reading battery info of {:?} pack", pack);
+
+    let cmd = battery_info_command(pack);
+
+    if pack == BatteryPack::Internal {
+      return self.request(&cmd, 2).await
+    }
+
+    match with_timeout(self.request(&cmd, 2), EXTERNAL_PACK_TIMEOUT).await {
+      Ok(Ok(info)) => Ok(info),
+      Ok(Err(err)) => {
+        self.discard_pending().await;
+        Err(anyhow!("No external battery: {}", err))
+      },
+      Err(_) => {
+        self.discard_pending().await;
+        Err(anyhow!("No external battery: no response in {:?}", EXTERNAL_PACK_TIMEOUT))
+      }
+    }
   }
 }
@@ -0,0 +1,63 @@
+use super::MiSession;
+use super::commands::Attribute;
+
+use anyhow::Result;
+
+/**
+ * What connected firmware answers to. M365 and clone firmwares captured so far use the same registers and
+ * offsets in every version, they differ in which registers exist at all (e.g. older firmwares have no zero start
+ * register 0x7E, clones often skip cell voltages). So compatibility is probed instead of guessed from version
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirmwareCompat {
+  /**
+   * Controller firmware version, e.g. "1.5.6"
+   */
+  pub version: String,
+  /**
+   * Readable attributes which did not answer when probed
+   */
+  pub unsupported: Vec<Attribute>,
+}
+
+impl FirmwareCompat {
+  /**
+   * False only for attributes which were probed and did not answer, Raw and write only attributes are
+   * never probed and are always allowed
+   */
+  pub fn supports(&self, attribute: &Attribute) -> bool {
+    !self.unsupported.contains(attribute)
+  }
+}
+
+impl MiSession {
+  /**
+   * Read firmware version and probe every known attribute, then remember result in session: reads of
+   * unsupported attributes fail right away instead of waiting for timeout. Probing takes a few seconds on
+   * scooters which miss many registers, use set_firmware_compat to reuse result saved from earlier connection.
+   * Attribute is listed as unsupported only when it misses two probes in a row, link errors fail whole check.
+   * Result is sticky: reads of listed attributes keep failing until firmware_compat runs again or compat is cleared
+   * with set_firmware_compat(None)
+   */
+  pub async fn firmware_compat(&mut self) -> Result<FirmwareCompat> {
+    tracing::debug!("Checking firmware compatibility");
+    self.set_firmware_compat(None);
+
+    let version = self.firmware_version().await?;
+    let mut unsupported = Vec::new();
+    for attribute in Attribute::all() {
+      self.ensure_authenticated()?;
+      // single miss can be BLE hiccup, so it is probed again before attribute is listed
+      let supported = self.try_probe(&attribute).await? || self.try_probe(&attribute).await?;
+      tracing::debug!("Attribute {:?} supported: {}", attribute, supported);
+      if !supported {
+        unsupported.push(attribute);
+      }
+    }
+
+    let compat = FirmwareCompat { version, unsupported };
+    tracing::debug!("Firmware compatibility: {:?}", compat);
+    self.set_firmware_compat(Some(compat.clone()));
+    Ok(compat)
+  }
+}
@@ -0,0 +1,97 @@
+use super::MiSession;
+use super::info::MotorInfo;
+
+use std::time::Duration;
+use serde::Serialize;
+use futures::Stream;
+use futures::stream::{self, StreamExt};
+
+/**
+ * Error code reported by controller (0xB0), non zero while fault is active
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ScooterError {
+  pub code: u16,
+}
+
+/**
+ * Change of scooter state, see MiSession::events
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ScooterTelemetryEvent {
+  /**
+   * Speed in kilometers per hour
+   */
+  SpeedUpdate(f32),
+  /**
+   * Battery percentage
+   */
+  BatteryUpdate(u16),
+  Fault(ScooterError),
+  /**
+   * Error code went back to 0
+   */
+  FaultCleared,
+  /**
+   * Motor info could not be read, next poll tries again
+   */
+  ReadFailed(String),
+}
+
+/**
+ * Turn motor info readings into events, only values which changed since previous reading are reported.
+ * First reading reports speed and battery, and fault when there is one
+ */
+#[derive(Debug, Clone, Default)]
+pub struct EventTracker {
+  speed_kmh: Option<f32>,
+  battery_percent: Option<u16>,
+  error_code: u16,
+}
+
+impl EventTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn update(&mut self, motor: &MotorInfo, error_code: u16) -> Vec<ScooterTelemetryEvent> {
+    let mut events = Vec::new();
+
+    if self.speed_kmh.replace(motor.speed_kmh) != Some(motor.speed_kmh) {
+      events.push(ScooterTelemetryEvent::SpeedUpdate(motor.speed_kmh));
+    }
+    if self.battery_percent.replace(motor.battery_percent) != Some(motor.battery_percent) {
+      events.push(ScooterTelemetryEvent::BatteryUpdate(motor.battery_percent));
+    }
+
+    let previous_code = std::mem::replace(&mut self.error_code, error_code);
+    if error_code != previous_code {
+      events.push(match error_code {
+        0 => ScooterTelemetryEvent::FaultCleared,
+        code => ScooterTelemetryEvent::Fault(ScooterError { code }),
+      });
+    }
+
+    events
+  }
+}
+
+impl MiSession {
+  /**
+   * Poll motor info every interval and report what changed. Scooter sends nothing by itself, every event comes
+   * from response to this poll, so changes shorter than interval are not seen. Stream never ends by itself
+   */
+  pub fn events(&mut self, interval: Duration) -> impl Stream<Item = ScooterTelemetryEvent> + '_ {
+    let ticker = tokio::time::interval(interval);
+
+    stream::unfold((self, ticker, EventTracker::new()), |(session, mut ticker, mut tracker)| async move {
+      ticker.tick().await;
+      let events = match session.motor_info_raw().await {
+        // error code is the first word after 3 bytes of header
+        Ok((motor, raw)) => tracker.update(&motor, u16::from_le_bytes([raw[3], raw[4]])),
+        Err(err) => vec![ScooterTelemetryEvent::ReadFailed(err.to_string())],
+      };
+      Some((events, (session, ticker, tracker)))
+    }).flat_map(stream::iter)
+  }
+}
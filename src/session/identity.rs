@@ -0,0 +1,149 @@
+use super::MiSession;
+
+use anyhow::Result;
+use btleplug::api::Peripheral as _;
+use serde::Serialize;
+use uuid::Uuid;
+
+const XIAOMI_SERVICE_UUID : Uuid = Uuid::from_u128(0x0000fe95_0000_1000_8000_00805f9b34fb);
+const NUS_SERVICE_UUID : Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+
+/**
+ * Family of scooter, decides which command set is worth trying
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DeviceKind {
+  /**
+   * Xiaomi FE95 service, MiAuth and serial in xxxxx/xxxxxxxx format
+   */
+  GenuineXiaomi,
+  /**
+   * Talks M365 protocol, but serial or services don't look like genuine controller
+   */
+  Clone,
+  /**
+   * Ninebot ES family, serial starts with N
+   */
+  NinebotEs,
+  Unknown,
+}
+
+impl DeviceKind {
+  /**
+   * Classify scooter from advertised or discovered services, MiAuth result and serial number
+   */
+  pub fn classify(services: &[Uuid], mi_auth: bool, serial: Option<&str>) -> DeviceKind {
+    let has_xiaomi_service = services.contains(&XIAOMI_SERVICE_UUID);
+    let has_uart_service = services.contains(&NUS_SERVICE_UUID);
+    let serial = serial.map(str::trim).filter(|serial| !serial.is_empty());
+
+    match serial {
+      Some(serial) if is_ninebot_serial(serial) => DeviceKind::NinebotEs,
+      Some(serial) if has_xiaomi_service && mi_auth && is_xiaomi_serial(serial) => DeviceKind::GenuineXiaomi,
+      _ if mi_auth || has_xiaomi_service || (has_uart_service && serial.is_some()) => DeviceKind::Clone,
+      _ => DeviceKind::Unknown
+    }
+  }
+}
+
+/**
+ * Genuine M365 serials are 5 digits, slash and 8 digits, e.g. 16132/00095292
+ */
+fn is_xiaomi_serial(serial: &str) -> bool {
+  match serial.split_once('/') {
+    Some((model, number)) => {
+      model.len() == 5 && number.len() == 8 &&
+        model.chars().chain(number.chars()).all(|c| c.is_ascii_digit())
+    },
+    None => false
+  }
+}
+
+/**
+ * Ninebot serials are 14 upper case letters and digits starting with N, e.g. N2GSD1234C5678
+ */
+fn is_ninebot_serial(serial: &str) -> bool {
+  serial.len() == 14 && serial.starts_with('N') &&
+    serial.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/**
+ * Model of genuine Xiaomi scooter, decoded from first part of serial number (see SERIAL_PREFIXES).
+ * Only models with confirmed prefix are listed, Pro, 1S and Essential are added together with their prefix
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ScooterModel {
+  M365,
+  Unknown,
+}
+
+/**
+ * Serial prefix (digits before slash) of every model confirmed from real scooter. Only prefixes seen on a scooter
+ * of known model belong here, guessed ones would make apps pick wrong attribute map. Add new model together with
+ * serial and model of a real unit. Also listed in doc/protocol.md
+ */
+const SERIAL_PREFIXES : [(&str, ScooterModel); 1] = [
+  ("16132", ScooterModel::M365),
+];
+
+impl ScooterModel {
+  /**
+   * Model from serial in xxxxx/xxxxxxxx format, Unknown for prefixes not in SERIAL_PREFIXES and for other formats
+   */
+  pub fn from_serial(serial: &str) -> ScooterModel {
+    let serial = serial.trim();
+    if !is_xiaomi_serial(serial) {
+      return ScooterModel::Unknown
+    }
+
+    SERIAL_PREFIXES.iter()
+      .find(|(prefix, _)| serial.starts_with(prefix))
+      .map(|(_, model)| *model)
+      .unwrap_or(ScooterModel::Unknown)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceIdentity {
+  pub kind: DeviceKind,
+  pub serial: Option<String>,
+  pub services: Vec<Uuid>,
+}
+
+impl MiSession {
+  /**
+   * Classify connected scooter. Session exists only after MiAuth succeeded, so this looks at discovered
+   * services and serial format. Serial which can't be read is not an error, clones often don't answer to it
+   */
+  pub async fn identify(&mut self) -> Result<DeviceIdentity> {
+    tracing::debug!("Identifying scooter");
+
+    let services : Vec<Uuid> = self.device().services().iter()
+      .map(|service| service.uuid)
+      .collect();
+
+    let serial = match self.serial_number().await {
+      Ok(serial) => Some(serial),
+      Err(err) => {
+        tracing::debug!("Could not read serial: {}", err);
+        None
+      }
+    };
+
+    let kind = DeviceKind::classify(&services, true, serial.as_deref());
+    tracing::debug!("Scooter identified as {:?}", kind);
+
+    Ok(DeviceIdentity { kind, serial, services })
+  }
+
+  /**
+   * Read serial number and decode model from it, Unknown when prefix is not known yet
+   */
+  pub async fn model(&mut self) -> Result<ScooterModel> {
+    let serial = self.serial_number().await?;
+    let model = ScooterModel::from_serial(&serial);
+    tracing::debug!("Serial {} is {:?}", serial, model);
+
+    Ok(model)
+  }
+}
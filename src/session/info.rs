@@ -1,9 +1,12 @@
 use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use super::response::{Response, expect_attribute};
+use crate::util::Timeout;
 
 use std::time::Duration;
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use serde::Serialize;
+use bitflags::bitflags;
 
 #[derive(Debug, Serialize)]
 pub struct GeneralInfo {
@@ -12,6 +15,34 @@ pub struct GeneralInfo {
   version: String
 }
 
+/**
+ * Unit for presenting speed and distance. Controller always reports metric values (mm/h speed, meters),
+ * km / miles switch of dashboard and app only changes what is displayed and has no readable register,
+ * so MotorInfo fields are metric regardless of scooter setting
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Unit {
+  Km,
+  Miles
+}
+
+const KM_PER_MILE : f32 = 1.609344;
+
+impl Unit {
+  /**
+   * Convert kilometers (or km/h) to this unit
+   */
+  pub fn from_km(&self, km: f32) -> f32 {
+    match self {
+      Unit::Km => km,
+      Unit::Miles => km / KM_PER_MILE
+    }
+  }
+}
+
+/**
+ * All values are metric, see Unit for converting to miles
+ */
 #[derive(Debug, Serialize)]
 pub struct MotorInfo {
   /**
@@ -30,10 +61,11 @@ pub struct MotorInfo {
    * Distance is in meters
    */
   pub total_distance_m: u32,
-    /**
-   * Distance is in meters
+  /**
+   * Distance is in meters. Register is unsigned 16 bit, so it wraps to 0 after 65 535m,
+   * use TripCounter when polling longer rides
    */
-  pub trip_distance_m: i16,
+  pub trip_distance_m: u32,
   pub uptime: Duration,
   /**
    * Temperature in celsius
@@ -41,6 +73,118 @@ pub struct MotorInfo {
   pub frame_temperature: f32
 }
 
+/**
+ * Trip register wraps back to 0 after this many meters
+ */
+const TRIP_REGISTER_SPAN_M : u32 = 0x1_0000;
+
+/**
+ * Readings closer to the end / start of register than this are treated as rollover instead of trip reset
+ */
+const TRIP_ROLLOVER_WINDOW_M : u32 = 1000;
+
+/**
+ * Extend 16 bit trip distance of MotorInfo past 65 535m. Feed it every reading, it counts rollovers
+ * by looking at drop from end of register to its start. Any other drop is treated as trip reset
+ */
+#[derive(Debug, Clone, Default)]
+pub struct TripCounter {
+  last_m: Option<u32>,
+  rollovers: u32,
+}
+
+impl TripCounter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * Update counter with trip distance reported by scooter, returns full trip distance in meters
+   */
+  pub fn update(&mut self, trip_distance_m: u32) -> u32 {
+    if let Some(last_m) = self.last_m {
+      if trip_distance_m < last_m {
+        if last_m >= TRIP_REGISTER_SPAN_M - TRIP_ROLLOVER_WINDOW_M && trip_distance_m < TRIP_ROLLOVER_WINDOW_M {
+          tracing::debug!("Trip distance rolled over: {}m -> {}m", last_m, trip_distance_m);
+          self.rollovers += 1;
+        } else {
+          tracing::debug!("Trip distance reset: {}m -> {}m", last_m, trip_distance_m);
+          self.rollovers = 0;
+        }
+      }
+    }
+
+    self.last_m = Some(trip_distance_m);
+    self.rollovers * TRIP_REGISTER_SPAN_M + trip_distance_m
+  }
+}
+
+/**
+ * Sensors available only on some models, unsupported ones are None.
+ * No tire pressure register is known in M365 protocol (MiSession), so tire pressures are always None for now
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtendedSensors {
+  /**
+   * Pressure in bars
+   */
+  pub tire_pressure_front: Option<f32>,
+  pub tire_pressure_rear: Option<f32>,
+  /**
+   * Controller temperature in celsius
+   */
+  pub controller_temp: Option<f32>,
+}
+
+bitflags! {
+  /**
+   * Scooter state decoded from the beginning of motor info block (0xB0).
+   * Low 16 bits are copied from flags word (0xB2), high bits are set by this library
+   * from error code (0xB0), warning code (0xB1) and current speed (0xB5)
+   */
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+  pub struct StatusFlags: u32 {
+    const SPEED_LIMITED = 0x0001;
+    const LOCKED        = 0x0002;
+    const BEEPING       = 0x0004;
+    const CHARGING      = 0x0200;
+    const ACTIVATED     = 0x0800;
+
+    const ERROR         = 0x0001_0000;
+    const WARNING       = 0x0002_0000;
+    const RIDING        = 0x0004_0000;
+  }
+}
+
+impl TryFrom<Payload> for StatusFlags {
+  type Error = anyhow::Error;
+
+  fn try_from(payload: Payload) -> Result<Self, Self::Error> {
+    let mut payload = payload;
+    payload.pop_head()?;
+
+    let error_code = payload.pop_u16()?; // ---Var176=¿error?
+    let warning_code = payload.pop_u16()?; // ---Var177=¿warning?
+    let flags = payload.pop_u16()?; // ---Var178=¿flags?
+    payload.pad_bytes(2)?; // ---Var179=¿workmode?
+    payload.pad_bytes(2)?; // ---Var180=%batt
+    let speed = payload.pop_i16()?; // ---Var181=¿velocidad metros/h?
+
+    let mut status = StatusFlags::from_bits_retain(flags as u32);
+    status.set(StatusFlags::ERROR, error_code != 0);
+    status.set(StatusFlags::WARNING, warning_code != 0);
+    status.set(StatusFlags::RIDING, speed != 0);
+
+    Ok(status)
+  }
+}
+
+impl Response for StatusFlags {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    StatusFlags::try_from(expect_attribute(&attribute, Attribute::MotorInfo, payload)?)
+  }
+}
+
 impl TryFrom<Payload> for MotorInfo {
   type Error = anyhow::Error;
 
@@ -53,7 +197,7 @@ impl TryFrom<Payload> for MotorInfo {
     let speed_kmh = payload.pop_i16()? as f32 / 1000.0; // ---Var181=¿velocidad metros/h?=0x0000=0km/h
     let speed_average_kmh = payload.pop_u16()? as f32 / 1000.0; // ---Var182=¿velocidad prom m/h?=0x4650=18km/h
     let total_distance_m = payload.pop_u32()?; // ---Var183-184=m-total=0x0000088a=2.1km
-    let trip_distance_m = payload.pop_i16()?; // ---Var185=¿?=0x0005=5
+    let trip_distance_m = payload.pop_u16()? as u32; // ---Var185=m-trip=0x0005=5m
     let uptime_s = payload.pop_i16()?; // ---Var186=¿?=0x027c=636
     let frame_temperature = payload.pop_i16()? as f32 / 10.0; // 	---Var187=temp*10=0x0118=28°C
 
@@ -71,6 +215,36 @@ impl TryFrom<Payload> for MotorInfo {
   }
 }
 
+impl Response for MotorInfo {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    MotorInfo::try_from(expect_attribute(&attribute, Attribute::MotorInfo, payload)?)
+  }
+}
+
+impl Response for GeneralInfo {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    //          [                      SERIAL                          ][          PIN         ][ VER  ]
+    // payload: /x31/x36/x31/x33/x32/x2f/x30/x30/x30/x39/x35/x32/x39/x32/x30/x30/x30/x30/x30/x30/x38/x01
+    let mut payload = expect_attribute(&attribute, Attribute::GeneralInfo, payload)?;
+    payload.pop_head()?;
+
+    let serial = payload.pop_string_utf8(11)?;
+    let pin = payload.pop_string_utf8(6)?;
+    let version = payload.pop_string_utf8(2)?;
+
+    Ok(GeneralInfo { serial, pin, version })
+  }
+}
+
+fn motor_info_command() -> ScooterCommand {
+  ScooterCommand {
+    direction: Direction::MasterToMotor,
+    read_write: ReadWrite::Read,
+    attribute: Attribute::MotorInfo,
+    payload: vec![0x20]
+  }
+}
+
 impl MiSession {
   pub async fn general_info(&mut self) -> Result<GeneralInfo> {
     tracing::debug!("Reading general information");
@@ -82,18 +256,25 @@ impl MiSession {
       payload: vec![0x16]
     };
 
-    self.send(&cmd).await?;
-    //          [                      SERIAL                          ][          PIN         ][ VER  ]
-    // payload: /x31/x36/x31/x33/x32/x2f/x30/x30/x30/x39/x35/x32/x39/x32/x30/x30/x30/x30/x30/x30/x38/x01
-    let mut payload = self.read(2).await?;
+    self.request(&cmd, 2).await
+  }
 
-    payload.pop_head()?;
+  /**
+   * Read len bytes of general info block starting offset bytes after its beginning (0x10), for fields which are
+   * not modeled by this library. Registers hold 16 bit words, so byte offset 0x14 is register 0x1A
+   * (firmware version) and odd offsets start in the middle of register
+   */
+  pub async fn general_info_field(&mut self, offset: u8, len: u8) -> Result<Vec<u8>> {
+    tracing::debug!("Reading general info field: offset {}, len {}", offset, len);
 
-    let serial = payload.pop_string_utf8(11)?;
-    let pin = payload.pop_string_utf8(6)?;
-    let version = payload.pop_string_utf8(2)?;
+    let register = Attribute::GeneralInfo.value() + offset / 2;
+    let skip = (offset % 2) as usize;
+    let read_len = len.checked_add(skip as u8)
+      .filter(|_| len > 0)
+      .ok_or_else(|| anyhow!("Invalid general info field length: {}", len))?;
 
-    Ok(GeneralInfo { serial, pin, version })
+    let bytes = self.read_attribute(Direction::MasterToMotor, Attribute::Raw(register), read_len).await?;
+    Ok(bytes[skip..].to_vec())
   }
 
   /**
@@ -117,18 +298,85 @@ impl MiSession {
     Ok(serial)
   }
 
+  /**
+   * Read controller (DRV) firmware version, register 0x1A holds one nibble per digit, e.g. 0x0156 is 1.5.6
+   */
+  pub async fn firmware_version(&mut self) -> Result<String> {
+    tracing::debug!("Reading firmware version");
+
+    let bytes = self.read_attribute(Direction::MasterToMotor, Attribute::FirmwareVersion, 2).await?;
+    let version = u16::from_le_bytes([bytes[0], bytes[1]]);
+
+    Ok(format!("{}.{}.{}", (version >> 8) & 0x0f, (version >> 4) & 0x0f, version & 0x0f))
+  }
+
   pub async fn motor_info(&mut self) -> Result<MotorInfo> {
     tracing::debug!("Reading motor info");
 
-    self.send(&ScooterCommand {
+    self.request(&motor_info_command(), 3).await
+  }
+
+  /**
+   * Motor info with decrypted response it was decoded from, for diagnostics of wrong readings
+   */
+  pub async fn motor_info_raw(&mut self) -> Result<(MotorInfo, Vec<u8>)> {
+    tracing::debug!("Reading motor info with raw response");
+
+    self.request_raw(&motor_info_command(), 3).await
+  }
+
+  /**
+   * Temperature of motor controller (ESC) in celsius, register 0x3E holds signed tenths of degree.
+   * Fails when controller does not answer to this register instead of returning 0
+   */
+  pub async fn controller_temperature(&mut self) -> Result<f32> {
+    tracing::debug!("Reading controller temperature");
+
+    let bytes = self.read_attribute(Direction::MasterToMotor, Attribute::ControllerTemp, 2).await
+      .with_context(|| "Controller temperature (0x3E) is not supported")?;
+
+    Ok(i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 10.0)
+  }
+
+  /**
+   * Read sensors which are not part of every model. Registers found unsupported by firmware_compat are skipped,
+   * register which does not answer in time is reported as None, so this does not fail on scooters without extra
+   * sensors. Other errors (e.g. SessionError::NotAuthenticated) are returned
+   */
+  pub async fn extended_sensors(&mut self) -> Result<ExtendedSensors> {
+    tracing::debug!("Reading extended sensors");
+
+    let controller_temp = if self.supports(&Attribute::ControllerTemp) {
+      match self.controller_temperature().await {
+        Ok(temperature) => Some(temperature),
+        Err(err) if err.is::<Timeout>() => {
+          tracing::debug!("{:#}", err);
+          None
+        },
+        Err(err) => return Err(err)
+      }
+    } else {
+      None
+    };
+
+    Ok(ExtendedSensors {
+      tire_pressure_front: None,
+      tire_pressure_rear: None,
+      controller_temp,
+    })
+  }
+
+  /**
+   * Read status flags: locked, charging, error present, riding, etc. in single request
+   */
+  pub async fn status_flags(&mut self) -> Result<StatusFlags> {
+    tracing::debug!("Reading status flags");
+
+    self.request(&ScooterCommand {
       direction: Direction::MasterToMotor,
       read_write: ReadWrite::Read,
       attribute: Attribute::MotorInfo,
-      payload: vec![0x20]
-    }).await?;
-
-    let payload = self.read(3).await?;
-
-    MotorInfo::try_from(payload)
+      payload: vec![0x0C]
+    }, 2).await
   }
 }
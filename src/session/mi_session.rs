@@ -1,41 +1,780 @@
 pub use super::payload::Payload;
-use super::commands::ScooterCommand;
-use crate::protocol::MiProtocol;
-use crate::mi_crypto::{encrypt_uart, decrypt_uart, LoginKeychain};
+use super::commands::{ScooterCommand, Attribute, Direction, ReadWrite};
+use super::response::Response;
+use super::travel::RangeModel;
+use super::compat::FirmwareCompat;
+use crate::protocol::{MiProtocol, NB_NOTIFICATION_TIMEOUT};
+use crate::mi_crypto::{encrypt_uart_into, decrypt_uart, decrypt_uart_into, AuthToken, LoginKeychain};
+use crate::connection::ConnectionHelper;
+use crate::login::LoginRequest;
 use crate::consts::Registers;
+use crate::scanner::find_central;
+use crate::util::{with_timeout, Timeout};
 
-use anyhow::Result;
-use btleplug::platform::Peripheral;
+use anyhow::{Context, Result, anyhow};
+use std::future::Future;
+use std::pin::Pin;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tokio::task::AbortHandle;
+use futures::Stream;
+use futures::stream::StreamExt;
+use btleplug::platform::{Manager, Peripheral};
+use btleplug::api::{Central, CentralEvent, Peripheral as _, WriteType};
+use uuid::Uuid;
+use serde::Serialize;
+use thiserror::Error;
 
+/**
+ * How long probed attribute can take to respond
+ */
+const PROBE_TIMEOUT : Duration = Duration::from_millis(1500);
+const PROBE_QUIET_PERIOD : Duration = Duration::from_millis(300);
+
+/**
+ * Bytes around decrypted response in notifications: header, length, counter, random, MIC and checksum.
+ * Scooter sends notifications of up to 20 bytes
+ */
+const RESPONSE_OVERHEAD : usize = 20;
+const NOTIFICATION_SIZE : usize = 20;
+
+/**
+ * Minimal gap between commands, slower controllers drop responses when commands come back to back
+ */
+const DEFAULT_COMMAND_PACING : Duration = Duration::from_millis(30);
+
+/**
+ * Commands sent after this long without traffic are preceded by wake
+ */
+const IDLE_WAKE_AFTER : Duration = Duration::from_secs(60);
+
+/**
+ * Controller is often still waking BLE stack after login and does not answer first command,
+ * it is sent again after this delay
+ */
+const FIRST_COMMAND_RETRY_DELAY : Duration = Duration::from_millis(200);
+const DEFAULT_FIRST_COMMAND_RETRIES : u32 = 1;
+
+/**
+ * Number of last responses used for average latency
+ */
+const LATENCY_WINDOW : usize = 32;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SessionError {
+  #[error("Scooter has no characteristic {expected}, found: {}", format_uuids(.found))]
+  MissingCharacteristic { expected: Uuid, found: Vec<Uuid> },
+  #[error("Scooter did not apply {attribute:?}: wrote 0x{written:04x}, read back 0x{read:04x}")]
+  WriteNotApplied { attribute: Attribute, written: u16, read: u16 },
+  #[error("Session is not authenticated, link dropped since login. Call resume or login again")]
+  NotAuthenticated,
+}
+
+fn format_uuids(uuids: &[Uuid]) -> String {
+  if uuids.is_empty() {
+    return "none".to_owned()
+  }
+  uuids.iter().map(Uuid::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/**
+ * Check that discovered services of device contain every characteristic in required, e.g. before login
+ * to fail early on scooter models with other characteristic set. MiSession::new checks UART TX and RX
+ */
+pub fn verify_characteristics(device: &Peripheral, required: &[Uuid]) -> Result<(), SessionError> {
+  let found : Vec<Uuid> = device.characteristics().iter()
+    .map(|characteristic| characteristic.uuid)
+    .collect();
+
+  match required.iter().find(|uuid| !found.contains(uuid)) {
+    Some(expected) => Err(SessionError::MissingCharacteristic { expected: *expected, found }),
+    None => Ok(())
+  }
+}
+
+/**
+ * Link health of session. Counters are since session started, latency is average of last few responses
+ * measured from sending command to receiving whole response
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LinkStats {
+  pub success: u64,
+  pub timeouts: u64,
+  pub avg_latency: Duration,
+  /**
+   * How many times link dropped and came back, and resume subscribed to notifications again
+   */
+  pub resubscribes: u64,
+}
+
+/**
+ * Stops watching for link drops together with session
+ */
+struct DropWatch(AbortHandle);
+
+impl Drop for DropWatch {
+  fn drop(&mut self) {
+    self.0.abort();
+  }
+}
+
+/**
+ * Encrypted command session with logged in scooter. Session is Send, but every command is request and response
+ * over one notification stream, so methods take &mut self and commands from different tasks must not interleave.
+ * To share it, wrap it in tokio::sync::Mutex (not std Mutex, its guard would be held across await and block
+ * runtime thread while scooter answers).
+ * Session watches for link drops: scooter forgets login keys on disconnect and answers commands encrypted with
+ * them with garbage or not at all, so after a drop commands fail with SessionError::NotAuthenticated without
+ * being sent, until resume logs in again. Responses and notifications sent during the drop are lost
+ */
 pub struct MiSession {
   protocol: MiProtocol,
   keys: LoginKeychain,
+  range_model: RangeModel,
+  command_pacing: Duration,
+  last_command: Option<Instant>,
+  link_stats: LinkStats,
+  latencies: VecDeque<Duration>,
+  token: Option<AuthToken>,
+  compat: Option<FirmwareCompat>,
+  verify_writes: bool,
+  first_command_retries: u32,
+  /**
+   * No response was read since login yet
+   */
+  first_command: bool,
+  link_dropped: Arc<AtomicBool>,
+  /**
+   * Set by login (session is created logged in), cleared when link drops
+   */
+  authenticated: Arc<AtomicBool>,
+  _drop_watch: DropWatch,
+  /**
+   * Reused for every command and response, see read_into
+   */
+  command_buffer: Vec<u8>,
+  frame_buffer: Vec<u8>,
 }
 
 impl MiSession {
+  /**
+   * Start session over UART characteristics, device has to be logged in and its services discovered.
+   * Fails with SessionError::MissingCharacteristic when TX or RX is not there
+   */
   pub async fn new(device: &Peripheral, keys: &LoginKeychain) -> Result<Self> {
+    verify_characteristics(device, &[Registers::TX.to_uuid(), Registers::RX.to_uuid()])?;
     let protocol = MiProtocol::new(device).await?;
     let keys = keys.clone();
+    let link_dropped = Arc::new(AtomicBool::new(false));
+    let authenticated = Arc::new(AtomicBool::new(true));
+    let drop_watch = tokio::spawn(watch_link_drops(device.clone(), link_dropped.clone(), authenticated.clone())).abort_handle();
+
+    Ok(Self {
+      protocol,
+      keys,
+      range_model: RangeModel::default(),
+      command_pacing: DEFAULT_COMMAND_PACING,
+      last_command: None,
+      link_stats: LinkStats::default(),
+      latencies: VecDeque::with_capacity(LATENCY_WINDOW),
+      token: None,
+      compat: None,
+      verify_writes: false,
+      first_command_retries: DEFAULT_FIRST_COMMAND_RETRIES,
+      first_command: true,
+      link_dropped,
+      authenticated,
+      _drop_watch: DropWatch(drop_watch),
+      command_buffer: Vec::new(),
+      frame_buffer: Vec::new(),
+    })
+  }
+
+  /**
+   * Remember token used for login, so resume can login again
+   */
+  pub(crate) fn with_token(mut self, token: &AuthToken) -> Self {
+    self.token = Some(*token);
+    self
+  }
+
+  /**
+   * Enable notifications again after unsubscribe. Session is subscribed when it is created
+   */
+  pub async fn subscribe(&mut self) -> Result<()> {
+    tracing::debug!("Subscribing to notifications");
+    self.protocol.subscribe().await
+  }
 
-    Ok(Self { protocol, keys })
+  /**
+   * Disable notifications of session, commands can't be read until subscribe is called again.
+   * Dropping session does not unsubscribe (it can't await and new session for the same scooter may be
+   * subscribed already), so call this or close before replacing session in long running apps, otherwise
+   * some platforms pile up stale subscriptions over reconnects and start dropping notifications
+   */
+  pub async fn unsubscribe(&mut self) -> Result<()> {
+    tracing::debug!("Unsubscribing from notifications");
+    self.protocol.dispose().await?;
+    Ok(())
+  }
+
+  /**
+   * Unsubscribe and drop session, connection itself is left open
+   */
+  pub async fn close(mut self) -> Result<()> {
+    self.unsubscribe().await
   }
 
   /**
-   * Serialize, encrypt and send command to scooter
+   * Bring session back after connection problems with as few round trips as possible.
+   * MiAuth has no lighter re-login than login with token (key exchange happens only at registration),
+   * so when scooter is still connected and answers with current keys nothing is sent at all, otherwise
+   * link is connected again (without disconnect and reconnect delay) and session logs in with cached token.
+   * When that fails too, full reconnect and login are done. After link drop notifications are subscribed again
+   * before probing, some clones keep keys over brief drops
    */
+  pub async fn resume(&mut self) -> Result<()> {
+    let device = self.protocol.device().clone();
+
+    if device.is_connected().await.unwrap_or(false) && self.resubscribe().await.is_ok() && self.probe(&Attribute::BatteryPercent).await {
+      tracing::debug!("Session still alive, keeping keys");
+      self.authenticated.store(true, Ordering::SeqCst);
+      return Ok(())
+    }
+
+    let token = self.token
+      .ok_or_else(|| anyhow!("Session was not created by LoginRequest, token for login is unknown"))?;
+
+    if let Err(err) = self.protocol.dispose().await {
+      tracing::debug!("Could not unsubscribe old session: {}", err);
+    }
+    let connection = ConnectionHelper::new(&device).with_required_service(Some(Registers::AUTH.to_uuid()));
+
+    let keys = match Self::login_again(&connection, &device, &token, false).await {
+      Ok(keys) => keys,
+      Err(err) => {
+        tracing::debug!("Quick resume failed: {}, doing full reconnect", err);
+        Self::login_again(&connection, &device, &token, true).await?
+      }
+    };
+
+    // Protocol is kept, it holds UART characteristics and write type set by with_characteristics and set_write_type
+    self.protocol.resubscribe().await?;
+    if self.link_dropped.swap(false, Ordering::SeqCst) {
+      self.link_stats.resubscribes += 1;
+    }
+    self.keys = keys;
+    self.last_command = None;
+    self.first_command = true;
+    self.authenticated.store(true, Ordering::SeqCst);
+    Ok(())
+  }
+
+  async fn login_again(connection: &ConnectionHelper, device: &Peripheral, token: &AuthToken, full: bool) -> Result<LoginKeychain> {
+    if full {
+      connection.reconnect().await?;
+    } else {
+      connection.connect().await?;
+    }
+
+    LoginRequest::new(device, token).await?.login_keys().await
+  }
+
+  /**
+   * Send commands to tx_uuid and read responses from rx_uuid instead of Nordic UART characteristics
+   * (6e400002/6e400003), e.g. for clones which use FE95 0x10/0x19 channels.
+   */
+  pub async fn with_characteristics(mut self, tx_uuid: Uuid, rx_uuid: Uuid) -> Result<Self> {
+    self.protocol.set_uart_characteristics(tx_uuid, rx_uuid).await?;
+    Ok(self)
+  }
+
+  /**
+   * Force write type used for commands. By default it is picked from TX characteristic properties.
+   * WriteType::WithResponse is slower, because every chunk waits for acknowledgment, but some clones
+   * ignore commands sent without response
+   */
+  pub fn set_write_type(&mut self, write_type: WriteType) {
+    self.protocol.set_write_type(write_type);
+  }
+
+  /**
+   * Set minimal delay between two commands sent by this session, default is 30ms.
+   * Duration::ZERO disables pacing
+   */
+  pub fn set_command_pacing(&mut self, pacing: Duration) {
+    self.command_pacing = pacing;
+  }
+
+  pub fn link_stats(&self) -> LinkStats {
+    self.link_stats
+  }
+
+  /**
+   * False after link dropped, until resume logs in again
+   */
+  pub fn is_authenticated(&self) -> bool {
+    self.authenticated.load(Ordering::SeqCst)
+  }
+
+  /**
+   * Read every setting back after writing it, setters fail with SessionError::WriteNotApplied when scooter
+   * kept old value. Off by default: write command has no acknowledgment, so without this Ok only means
+   * that command was sent
+   */
+  pub fn set_verify_writes(&mut self, verify: bool) {
+    self.verify_writes = verify;
+  }
+
+  /**
+   * How many times first command after login is sent again when scooter does not answer it, default is 1.
+   * Controller often misses it while BLE stack is waking up, so retry avoids reconnecting right after login.
+   * 0 reports first timeout right away. Probes (resume, firmware compat) don't retry and leave it to the next command
+   */
+  pub fn set_first_command_retries(&mut self, retries: u32) {
+    self.first_command_retries = retries;
+  }
+
+  /**
+   * False only when firmware_compat found attribute unsupported, without compat every attribute is assumed supported
+   */
+  pub(super) fn supports(&self, attribute: &Attribute) -> bool {
+    self.compat.as_ref().is_none_or(|compat| compat.supports(attribute))
+  }
+
+  /**
+   * Compatibility used to reject reads of unsupported attributes, None (default) sends every command
+   */
+  pub fn set_firmware_compat(&mut self, compat: Option<FirmwareCompat>) {
+    self.compat = compat;
+  }
+
+  pub fn range_model(&self) -> &RangeModel {
+    &self.range_model
+  }
+
+  /**
+   * Replace consumption model used by estimated_range
+   */
+  pub fn set_range_model(&mut self, model: RangeModel) {
+    self.range_model = model;
+  }
+
+  /**
+   * Serialize, encrypt and send command to scooter. When session was idle for IDLE_WAKE_AFTER,
+   * wake is tried first so sleeping scooter is reported in log instead of as random timeout.
+   * Reads of attributes which firmware_compat found unsupported fail without sending anything, so does every
+   * command after link dropped (SessionError::NotAuthenticated)
+   */
+  #[tracing::instrument(skip(self, cmd), fields(attribute = ?cmd.attribute))]
   pub async fn send(&mut self, cmd: &ScooterCommand) -> Result<bool> {
-    let bytes = encrypt_uart(&self.keys.app, &cmd.as_bytes(), 0, None); // encrypt bytes
-    self.protocol.write_nb_parcel(&Registers::TX, &bytes).await?;
+    if let Some(compat) = self.compat.as_ref().filter(|_| matches!(cmd.read_write, ReadWrite::Read)) {
+      if !compat.supports(&cmd.attribute) {
+        return Err(anyhow!("{:?} is not supported by firmware {}", cmd.attribute, compat.version))
+      }
+    }
+
+    self.ensure_authenticated()?;
+
+    let idle = self.last_command.is_some_and(|last_command| last_command.elapsed() >= IDLE_WAKE_AFTER);
+    if idle && !self.wake().await? {
+      tracing::warn!("Scooter does not respond after being idle, it is probably asleep or turned off");
+    }
+
+    self.send_now(cmd).await
+  }
+
+  /**
+   * Check if controller answers, with cheap read of battery percentage which also keeps link busy.
+   * There is no documented wake command and controller can't be powered on over bluetooth: when this returns
+   * false, scooter has to be turned on physically (BLE module of clones stays connected while controller sleeps)
+   */
+  pub async fn wake(&mut self) -> Result<bool> {
+    tracing::debug!("Waking scooter");
+    Ok(self.probe(&Attribute::BatteryPercent).await)
+  }
+
+  /**
+   * Round trip of the smallest read (battery percentage), response is only checked and not parsed.
+   * Call it periodically to keep link busy and verify that scooter answers, latency is recorded in link_stats.
+   * Idle wake is skipped, so returned latency is one command and one response
+   */
+  pub async fn ping(&mut self) -> Result<Duration> {
+    let attribute = Attribute::BatteryPercent;
+    let cmd = ScooterCommand {
+      direction: attribute.direction(),
+      read_write: ReadWrite::Read,
+      attribute: attribute.clone(),
+      payload: vec![0x02]
+    };
+
+    self.ensure_authenticated()?;
+    let started = Instant::now();
+    self.send_now(&cmd).await?;
+    let response = match self.read_bytes(2).await {
+      Ok(response) => response,
+      Err(err) => {
+        self.discard_pending().await;
+        return Err(err)
+      }
+    };
+    let latency = started.elapsed();
+
+    match response.get(2) {
+      Some(received) if *received == attribute.value() => {
+        tracing::debug!("Ping answered in {:?}", latency);
+        Ok(latency)
+      },
+      Some(received) => Err(anyhow!("Expected response for {:?}, but received attribute 0x{:02x}", attribute, received)),
+      None => Err(anyhow!("Response is too short: {} bytes", response.len()))
+    }
+  }
+
+  pub(super) fn ensure_authenticated(&self) -> Result<(), SessionError> {
+    if self.is_authenticated() {
+      Ok(())
+    } else {
+      Err(SessionError::NotAuthenticated)
+    }
+  }
+
+  /**
+   * When link dropped since last command, subscribe to notifications again on fresh stream, old one ended with
+   * the drop. Notifications sent while link was down are lost
+   */
+  async fn resubscribe(&mut self) -> Result<()> {
+    if !self.link_dropped.swap(false, Ordering::SeqCst) {
+      return Ok(())
+    }
+
+    tracing::debug!("Link dropped since last command, subscribing to notifications again");
+    self.protocol.resubscribe().await?;
+    self.link_stats.resubscribes += 1;
+    Ok(())
+  }
+
+  async fn send_now(&mut self, cmd: &ScooterCommand) -> Result<bool> {
+    if let Some(last_command) = self.last_command {
+      let elapsed = last_command.elapsed();
+      if elapsed < self.command_pacing {
+        time::sleep(self.command_pacing - elapsed).await;
+      }
+    }
+    self.last_command = Some(Instant::now());
+
+    cmd.write_bytes(&mut self.command_buffer);
+    encrypt_uart_into(&self.keys.app, &self.command_buffer, 0, None, &mut self.frame_buffer); // encrypt bytes
+    self.protocol.write_nb_parcel(&Registers::TX, &self.frame_buffer).await?;
     Ok(true)
   }
 
+  /**
+   * Send last command again, it is still serialized in command_buffer
+   */
+  async fn resend(&mut self) -> Result<()> {
+    self.last_command = Some(Instant::now());
+    encrypt_uart_into(&self.keys.app, &self.command_buffer, 0, None, &mut self.frame_buffer);
+    self.protocol.write_nb_parcel(&Registers::TX, &self.frame_buffer).await?;
+    Ok(())
+  }
+
+  /**
+   * Write 16 bit setting register, with verify_writes read it back and compare
+   */
+  pub(super) async fn write_setting(&mut self, attribute: Attribute, value: u16) -> Result<()> {
+    self.send(&ScooterCommand {
+      direction: attribute.direction(),
+      read_write: ReadWrite::Write,
+      attribute: attribute.clone(),
+      payload: value.to_le_bytes().to_vec()
+    }).await?;
+
+    if !self.verify_writes {
+      return Ok(())
+    }
+
+    let bytes = self.read_attribute(attribute.direction(), attribute.clone(), 2).await?;
+    let read = u16::from_le_bytes([bytes[0], bytes[1]]);
+    if read != value {
+      return Err(SessionError::WriteNotApplied { attribute, written: value, read }.into())
+    }
+    tracing::debug!("Write of {:?} confirmed", attribute);
+    Ok(())
+  }
+
   /**
    * Wait for response from scooter. You can specify number of frames that you expect to receive
    */
   pub async fn read(&mut self, frames: u8) -> Result<Payload> {
-    let data = self.protocol.read_nb_parcel(frames).await?;
-    let response = decrypt_uart(&self.keys.dev, &data)?;
+    let response = self.read_bytes(frames).await?;
     let payload = Payload::from(response);
     Ok(payload)
   }
+
+  /**
+   * Send command and decode response into any type implementing Response trait
+   */
+  #[tracing::instrument(skip(self, cmd), fields(attribute = ?cmd.attribute))]
+  pub async fn request<T: Response>(&mut self, cmd: &ScooterCommand, frames: u8) -> Result<T> {
+    self.send(cmd).await?;
+    let response = self.read_bytes(frames).await?;
+    T::parse(cmd.attribute.clone(), &response)
+  }
+
+  /**
+   * Same as request, but returns decrypted response bytes next to decoded value, e.g. to include exact frame
+   * in bug report about wrong reading. When response can't be decoded, its bytes are in error context
+   */
+  pub async fn request_raw<T: Response>(&mut self, cmd: &ScooterCommand, frames: u8) -> Result<(T, Vec<u8>)> {
+    self.send(cmd).await?;
+    let response = self.read_bytes(frames).await?;
+    let value = T::parse(cmd.attribute.clone(), &response)
+      .with_context(|| format!("Raw response {:02X?}", response))?;
+    Ok((value, response))
+  }
+
+  /**
+   * Send command and decrypt response into buf, for loggers polling many times a second. Buffers for command,
+   * encrypted frames and response are reused, so with command built once there are no allocations per read.
+   * Returns whole response (decode it with Response::parse), after checking that it answers cmd.attribute
+   */
+  #[tracing::instrument(skip(self, cmd, buf), fields(attribute = ?cmd.attribute))]
+  pub async fn read_into<'b>(&mut self, cmd: &ScooterCommand, frames: u8, buf: &'b mut Vec<u8>) -> Result<&'b [u8]> {
+    self.send(cmd).await?;
+    self.read_frame_timeout(frames, NB_NOTIFICATION_TIMEOUT).await?;
+    decrypt_uart_into(&self.keys.dev, &self.frame_buffer, buf)?;
+
+    match buf.get(2) {
+      Some(received) if *received == cmd.attribute.value() => Ok(buf.as_slice()),
+      Some(received) => Err(anyhow!("Expected response for {:?}, but received attribute 0x{:02x}", cmd.attribute, received)),
+      None => Err(anyhow!("Response is too short: {} bytes", buf.len()))
+    }
+  }
+
+  /**
+   * Read len bytes of any attribute without typed wrapper, e.g. to experiment with registers not modeled by
+   * library (use Attribute::Raw). Returns payload after checking that scooter answered to requested attribute
+   */
+  pub async fn read_attribute(&mut self, direction: Direction, attribute: Attribute, len: u8) -> Result<Vec<u8>> {
+    self.read_attribute_timeout(direction, attribute, len, NB_NOTIFICATION_TIMEOUT).await
+  }
+
+  /**
+   * Same as read_attribute, but every notification of response has to arrive in timeout.
+   * Use short timeout for frequent polls (e.g. speed every 200ms) to fail fast and try again on next tick,
+   * after failed read late notifications are discarded
+   */
+  #[tracing::instrument(skip(self))]
+  pub async fn read_attribute_timeout(&mut self, direction: Direction, attribute: Attribute, len: u8, timeout: Duration) -> Result<Vec<u8>> {
+    let frames = (len as usize + RESPONSE_OVERHEAD).div_ceil(NOTIFICATION_SIZE) as u8;
+
+    self.send(&ScooterCommand {
+      direction,
+      read_write: ReadWrite::Read,
+      attribute: attribute.clone(),
+      payload: vec![len]
+    }).await?;
+
+    let response = match self.read_bytes_timeout(frames, timeout).await {
+      Ok(response) => response,
+      Err(err) => {
+        // late frames would be read as response to next command
+        self.discard_pending().await;
+        return Err(err)
+      }
+    };
+    match response.get(2) {
+      Some(received) if *received == attribute.value() => {},
+      Some(received) => return Err(anyhow!("Expected response for {:?}, but received attribute 0x{:02x}", attribute, received)),
+      None => return Err(anyhow!("Response is too short: {} bytes", response.len()))
+    }
+
+    response.get(3..3 + len as usize)
+      .map(|payload| payload.to_vec())
+      .ok_or_else(|| anyhow!("Expected {} bytes of {:?}, received {}", len, attribute, response.len().saturating_sub(3)))
+  }
+
+  /**
+   * Try to read every known attribute and check if scooter responds to it in PROBE_TIMEOUT.
+   * Use it to figure out which features are supported, e.g. clones often don't report cell voltages
+   */
+  pub async fn probe_attributes(&mut self) -> Vec<(Attribute, bool)> {
+    let mut capabilities = Vec::new();
+
+    for attribute in Attribute::all() {
+      let supported = self.probe(&attribute).await;
+      tracing::debug!("Attribute {:?} supported: {}", attribute, supported);
+      capabilities.push((attribute, supported));
+    }
+
+    capabilities
+  }
+
+  async fn probe(&mut self, attribute: &Attribute) -> bool {
+    self.try_probe(attribute).await.unwrap_or(false)
+  }
+
+  /**
+   * Same as probe, but only missing or wrong answer is Ok(false), link errors (e.g. failed write) are returned
+   */
+  pub(super) async fn try_probe(&mut self, attribute: &Attribute) -> Result<bool> {
+    let cmd = ScooterCommand {
+      direction: attribute.direction(),
+      read_write: ReadWrite::Read,
+      attribute: attribute.clone(),
+      payload: vec![0x02]
+    };
+
+    // PROBE_TIMEOUT is shorter than first command retry, so probe leaves it to the next command
+    let first_command = std::mem::take(&mut self.first_command);
+    let response = with_timeout(async {
+      self.send_now(&cmd).await?;
+      self.read_bytes(2).await
+    }, PROBE_TIMEOUT).await;
+
+    match response {
+      Ok(Ok(bytes)) => Ok(bytes.get(2) == Some(&attribute.value())),
+      Ok(Err(err)) if !err.is::<Timeout>() => {
+        self.first_command = first_command;
+        self.discard_pending().await;
+        Err(err)
+      },
+      _ => {
+        self.first_command = first_command;
+        self.discard_pending().await;
+        Ok(false)
+      }
+    }
+  }
+
+  /**
+   * Drop late frames of command which timed out, otherwise they would be read as response to next one
+   */
+  pub(super) async fn discard_pending(&mut self) {
+    self.protocol.discard_pending(PROBE_QUIET_PERIOD).await;
+  }
+
+  async fn read_bytes(&mut self, frames: u8) -> Result<Vec<u8>> {
+    self.read_bytes_timeout(frames, NB_NOTIFICATION_TIMEOUT).await
+  }
+
+  async fn read_bytes_timeout(&mut self, frames: u8, timeout: Duration) -> Result<Vec<u8>> {
+    self.read_frame_timeout(frames, timeout).await?;
+
+    let response = decrypt_uart(&self.keys.dev, &self.frame_buffer)?;
+    Ok(response)
+  }
+
+  /**
+   * Read encrypted response into frame_buffer. First command after login is sent again when it timed out,
+   * see set_first_command_retries
+   */
+  async fn read_frame_timeout(&mut self, frames: u8, timeout: Duration) -> Result<()> {
+    let mut retries = if std::mem::take(&mut self.first_command) { self.first_command_retries } else { 0 };
+    let mut resent = false;
+
+    loop {
+      match self.protocol.read_nb_parcel_into(frames, timeout, &mut self.frame_buffer).await {
+        Ok(_) => break,
+        Err(err) if err.is::<Timeout>() => {
+          self.link_stats.timeouts += 1;
+          if retries == 0 {
+            return Err(err)
+          }
+          retries -= 1;
+
+          tracing::debug!("No response to first command after login, sending it again");
+          time::sleep(FIRST_COMMAND_RETRY_DELAY).await;
+          // late answer or partial frame of previous send would be read as response
+          self.discard_pending().await;
+          self.resend().await?;
+          resent = true;
+        },
+        Err(err) => return Err(err)
+      }
+    }
+
+    if resent {
+      // scooter may answer both sends, second answer would be read by next command
+      self.discard_pending().await;
+    }
+
+    if let Some(sent_at) = self.last_command {
+      self.record_latency(sent_at.elapsed());
+    }
+    Ok(())
+  }
+
+  fn record_latency(&mut self, latency: Duration) {
+    if self.latencies.len() == LATENCY_WINDOW {
+      self.latencies.pop_front();
+    }
+    self.latencies.push_back(latency);
+
+    self.link_stats.success += 1;
+    self.link_stats.avg_latency = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+  }
+
+  pub(super) fn device(&self) -> &Peripheral {
+    self.protocol.device()
+  }
+
+  /**
+   * Future which resolves when bluetooth adapter reports that scooter got disconnected.
+   * Use it to start reconnecting right away, instead of waiting for next command to fail
+   */
+  pub fn on_disconnect(&self) -> impl Future<Output = ()> + Send + 'static {
+    wait_for_disconnect(self.protocol.device().clone())
+  }
+}
+
+async fn wait_for_disconnect(device: Peripheral) {
+  let id = device.id();
+
+  match adapter_events().await {
+    Ok(mut events) => {
+      // Scooter could drop before we started listening
+      if !device.is_connected().await.unwrap_or(false) {
+        return
+      }
+
+      while let Some(event) = events.next().await {
+        if let CentralEvent::DeviceDisconnected(peer_id) = event {
+          if peer_id == id {
+            tracing::debug!("Scooter disconnected: {:?}", peer_id);
+            return
+          }
+        }
+      }
+    },
+    Err(err) => tracing::warn!("Could not watch adapter events, polling connection instead: {}", err)
+  }
+
+  while device.is_connected().await.unwrap_or(false) {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+  }
+}
+
+/**
+ * Flag every disconnect of device, waits for it to be connected again before watching for next one
+ */
+async fn watch_link_drops(device: Peripheral, link_dropped: Arc<AtomicBool>, authenticated: Arc<AtomicBool>) {
+  loop {
+    wait_for_disconnect(device.clone()).await;
+    link_dropped.store(true, Ordering::SeqCst);
+    authenticated.store(false, Ordering::SeqCst);
+
+    while !device.is_connected().await.unwrap_or(false) {
+      tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+  }
+}
+
+async fn adapter_events() -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + Send>>> {
+  let manager = Manager::new().await?;
+  let central = find_central(&manager).await?;
+  Ok(central.events().await?)
 }
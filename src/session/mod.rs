@@ -4,9 +4,23 @@ mod info;
 mod travel;
 mod battery;
 mod payload;
+mod response;
 mod settings;
-pub use mi_session::MiSession;
+mod telemetry;
+mod identity;
+mod profile;
+mod compat;
+mod events;
+pub use mi_session::{MiSession, LinkStats, SessionError, verify_characteristics};
 pub use payload::Payload;
-pub use info::{GeneralInfo, MotorInfo};
-pub use settings::{TailLight};
-pub use battery::{BatteryInfo};
+pub use response::Response;
+pub use commands::{Attribute, Direction};
+pub use info::{GeneralInfo, MotorInfo, StatusFlags, ExtendedSensors, Unit, TripCounter};
+pub use settings::{Kers, TailLight, TailLightConfig, SpeedMode, SupplementaryInfo, CruiseConfig};
+pub use travel::{RangeModel, TripSummary};
+pub use battery::{BatteryInfo, BatteryPack, ChargeState};
+pub use telemetry::{Telemetry, Continuity, SessionContinuity};
+pub use identity::{DeviceIdentity, DeviceKind, ScooterModel};
+pub use profile::{ScooterProfile, ProfileSetting, ApplyReport};
+pub use compat::FirmwareCompat;
+pub use events::{ScooterTelemetryEvent, ScooterError, EventTracker};
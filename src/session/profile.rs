@@ -0,0 +1,93 @@
+use super::MiSession;
+use super::settings::{Kers, TailLight, SpeedMode};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+/**
+ * Saved scooter configuration, settings which are None are left as they are
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScooterProfile {
+  pub speed_mode: Option<SpeedMode>,
+  pub kers: Option<Kers>,
+  pub cruise: Option<bool>,
+  pub tail_light: Option<TailLight>,
+  /**
+   * Speed in kilometers per hour
+   */
+  pub speed_limit: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProfileSetting {
+  SpeedMode,
+  Kers,
+  Cruise,
+  TailLight,
+  SpeedLimit,
+}
+
+/**
+ * Outcome of MiSession::apply_profile, every setting from profile is either in applied or in failed
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ApplyReport {
+  pub applied: Vec<ProfileSetting>,
+  /**
+   * Setting with error message
+   */
+  pub failed: Vec<(ProfileSetting, String)>,
+}
+
+impl ApplyReport {
+  pub fn is_complete(&self) -> bool {
+    self.failed.is_empty()
+  }
+
+  fn record(&mut self, setting: ProfileSetting, result: Result<()>) {
+    match result {
+      Ok(()) => self.applied.push(setting),
+      Err(err) => {
+        tracing::debug!("Applying {:?} failed: {}", setting, err);
+        self.failed.push((setting, err.to_string()));
+      }
+    }
+  }
+}
+
+impl MiSession {
+  /**
+   * Write all settings of profile back to back, without reading them back in between. Failed setting does not
+   * stop the rest, check report for what was applied.
+   * Speed mode is switched only by button on M365 family and speed limit register (0x73) is not known
+   * to be writable on stock firmware, so these two always end up in failed
+   */
+  pub async fn apply_profile(&mut self, profile: &ScooterProfile) -> Result<ApplyReport> {
+    tracing::debug!("Applying profile: {:?}", profile);
+
+    let mut report = ApplyReport::default();
+
+    if let Some(mode) = profile.speed_mode {
+      report.record(ProfileSetting::SpeedMode, Err(anyhow!("Setting speed mode {:?} is not supported", mode)));
+    }
+
+    if let Some(level) = profile.kers {
+      report.record(ProfileSetting::Kers, self.set_kers(level).await);
+    }
+
+    if let Some(on) = profile.cruise {
+      report.record(ProfileSetting::Cruise, self.set_cruise(on).await);
+    }
+
+    if let Some(mode) = profile.tail_light {
+      report.record(ProfileSetting::TailLight, self.set_tail_light(mode).await);
+    }
+
+    if let Some(limit) = profile.speed_limit {
+      report.record(ProfileSetting::SpeedLimit, Err(anyhow!("Setting speed limit {} km/h is not supported", limit)));
+    }
+
+    Ok(report)
+  }
+}
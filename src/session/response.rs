@@ -0,0 +1,28 @@
+use super::Payload;
+use super::commands::Attribute;
+
+use anyhow::{Result, anyhow};
+
+/**
+ * Implement this trait for every struct that can be decoded from scooter response.
+ * Attribute is the one that was sent in command, so implementation can reject responses it does not understand
+ */
+pub trait Response: Sized {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self>;
+}
+
+/**
+ * Make sure that response was sent for expected attribute and wrap bytes into payload.
+ * Third byte of every decrypted response is attribute which scooter is answering to
+ */
+pub(crate) fn expect_attribute(attribute: &Attribute, expected: Attribute, bytes: &[u8]) -> Result<Payload> {
+  if *attribute != expected {
+    return Err(anyhow!("Can't parse response for {:?}, expected {:?}", attribute, expected))
+  }
+
+  match bytes.get(2) {
+    Some(received) if *received == expected.value() => Ok(Payload::from(bytes)),
+    Some(received) => Err(anyhow!("Expected response for {:?}, but received attribute 0x{:02x}", expected, received)),
+    None => Err(anyhow!("Response is too short: {} bytes", bytes.len()))
+  }
+}
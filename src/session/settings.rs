@@ -1,10 +1,12 @@
 use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use super::response::{Response, expect_attribute};
+use super::info::StatusFlags;
 
 use anyhow::Result;
 use serde::Serialize;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum Kers {
   Weak,
   Medium,
@@ -12,7 +14,14 @@ pub enum Kers {
   Unknown
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum SpeedMode {
+  Eco,
+  Drive,
+  Sport
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum TailLight {
   Off,
   OnBrake,
@@ -42,11 +51,79 @@ impl From<u16> for Kers {
   }
 }
 
+/**
+ * Cruise register 0x7C as u16. Stock firmware only uses bit 0 (cruise enabled, engages after holding
+ * steady throttle for ~5s, delay is not configurable). Remaining bits are kept in `reserved` untouched,
+ * so custom firmwares using them can be inspected and written back
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CruiseConfig {
+  pub enabled: bool,
+  pub reserved: u16,
+}
+
+const CRUISE_ENABLED : u16 = 0x0001;
+
+impl From<u16> for CruiseConfig {
+  fn from(value: u16) -> Self {
+    CruiseConfig {
+      enabled: value & CRUISE_ENABLED != 0,
+      reserved: value & !CRUISE_ENABLED,
+    }
+  }
+}
+
+impl From<CruiseConfig> for u16 {
+  fn from(config: CruiseConfig) -> Self {
+    let enabled = if config.enabled { CRUISE_ENABLED } else { 0 };
+    (config.reserved & !CRUISE_ENABLED) | enabled
+  }
+}
+
+/**
+ * Tail light register 0x7D as u16. Stock M365, Pro and 1S firmwares only use values 0-2 (off, on brake, always),
+ * there are no documented brake flash or auto brightness bits on any model. Flash on brake is a feature of custom
+ * firmwares, so bits above mode are kept in `reserved` untouched for them to be inspected and written back
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TailLightConfig {
+  pub mode: TailLight,
+  pub reserved: u16,
+}
+
+const TAIL_LIGHT_MODE_MASK : u16 = 0x0003;
+
+impl From<u16> for TailLightConfig {
+  fn from(value: u16) -> Self {
+    TailLightConfig {
+      mode: TailLight::from(value & TAIL_LIGHT_MODE_MASK),
+      reserved: value & !TAIL_LIGHT_MODE_MASK,
+    }
+  }
+}
+
+impl From<TailLightConfig> for u16 {
+  fn from(config: TailLightConfig) -> Self {
+    let mode = match config.mode {
+      TailLight::OnBrake => 0x01,
+      TailLight::Always => 0x02,
+      _ => 0x00
+    };
+    (config.reserved & !TAIL_LIGHT_MODE_MASK) | mode
+  }
+}
+
+/**
+ * Settings block read from 0x7B, one u16 per register:
+ * 0x7B KERS level (0 weak, 1 medium, 2 strong), 0x7C cruise (0/1), 0x7D tail light (0 off, 1 on brake, 2 always).
+ * Drive/beep flags are not part of this block on M365 firmwares. Settings don't share register, so there is no
+ * flags word to modify, set_cruise and set_tail_light keep unknown bits of their own register instead
+ */
 #[derive(Debug, Serialize)]
 pub struct SupplementaryInfo {
-  kers: Kers,
-  is_cruise: bool,
-  tail_light: TailLight
+  pub kers: Kers,
+  pub is_cruise: bool,
+  pub tail_light: TailLight
 }
 
 impl TryFrom<Payload> for SupplementaryInfo {
@@ -66,20 +143,22 @@ impl TryFrom<Payload> for SupplementaryInfo {
   }
 }
 
+impl Response for SupplementaryInfo {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    SupplementaryInfo::try_from(expect_attribute(&attribute, Attribute::Supplementary, payload)?)
+  }
+}
+
 impl MiSession {
   pub async fn supplementary_info(&mut self) -> Result<SupplementaryInfo> {
     tracing::debug!("Reading supplementary information");
 
-    self.send(&ScooterCommand {
+    self.request(&ScooterCommand {
       direction: Direction::MasterToBattery,
       read_write: ReadWrite::Read,
       attribute: Attribute::Supplementary,
       payload: vec![0x06]
-    }).await?;
-
-    let payload = self.read(2).await?;
-
-    Ok(SupplementaryInfo::try_from(payload)?)
+    }, 2).await
   }
 
   pub async fn is_cruise_on(&mut self) -> Result<bool> {
@@ -98,6 +177,74 @@ impl MiSession {
     Ok(payload.pop_bool()?)
   }
 
+  /**
+   * Read whole cruise register (0x7C), including bits not covered by is_cruise_on
+   */
+  pub async fn cruise_config(&mut self) -> Result<CruiseConfig> {
+    tracing::debug!("Reading cruise config");
+
+    self.send(&ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::Cruise,
+      payload: vec![0x02]
+    }).await?;
+
+    let mut payload = self.read(2).await?;
+    payload.pop_head()?;
+
+    Ok(CruiseConfig::from(payload.pop_u16()?))
+  }
+
+  pub async fn set_cruise_config(&mut self, config: CruiseConfig) -> Result<()> {
+    tracing::debug!("Setting cruise config: {:?}", config);
+
+    self.write_setting(Attribute::Cruise, config.into()).await
+  }
+
+  /**
+   * Anti-theft lock set from app (LOCKED flag of 0xB2). Locked scooter brakes and beeps when pushed.
+   * M365 has no separate ride lock, so this is the only lock there is
+   */
+  pub async fn antitheft_status(&mut self) -> Result<bool> {
+    tracing::debug!("Reading anti-theft status");
+
+    Ok(self.status_flags().await?.contains(StatusFlags::LOCKED))
+  }
+
+  /**
+   * Enable (write 0x70) or disable (write 0x71) anti-theft lock
+   */
+  pub async fn set_antitheft(&mut self, enabled: bool) -> Result<()> {
+    tracing::debug!("Setting anti-theft: {}", enabled);
+
+    let attribute = if enabled { Attribute::Lock } else { Attribute::Unlock };
+
+    self.send(&ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Write,
+      attribute,
+      payload: vec![0x01, 0x00]
+    }).await?;
+
+    Ok(())
+  }
+
+  /**
+   * Set regenerative braking level, first register of supplementary block (0x7B)
+   */
+  pub async fn set_kers(&mut self, level: Kers) -> Result<()> {
+    tracing::debug!("Setting KERS: {:?}", level);
+
+    let level : u16 = match level {
+      Kers::Medium => 0x01,
+      Kers::Strong => 0x02,
+      _ => 0x00
+    };
+
+    self.write_setting(Attribute::Supplementary, level).await
+  }
+
   pub async fn tail_light(&mut self) -> Result<TailLight> {
     tracing::debug!("Reading tail light state");
 
@@ -116,43 +263,78 @@ impl MiSession {
     )
   }
 
-  pub async fn set_tail_light(&mut self, mode : TailLight) -> Result<()> {
-    tracing::debug!("Setting tail light: {:?}", mode);
-
-    let mode : u8 = match mode {
-      TailLight::OnBrake => 0x01,
-      TailLight::Always => 0x02,
-      _ => 0x00
-    };
-
-    let payload = vec![mode, 0x00];
+  /**
+   * Read whole tail light register (0x7D), including bits not covered by tail_light
+   */
+  pub async fn tail_light_config(&mut self) -> Result<TailLightConfig> {
+    tracing::debug!("Reading tail light config");
 
     self.send(&ScooterCommand {
       direction: Direction::MasterToMotor,
-      read_write: ReadWrite::Write,
+      read_write: ReadWrite::Read,
       attribute: Attribute::TailLight,
-      payload
+      payload: vec![0x02]
     }).await?;
 
-    Ok(())
+    let mut payload = self.read(2).await?;
+    payload.pop_head()?;
+
+    Ok(TailLightConfig::from(payload.pop_u16()?))
+  }
+
+  pub async fn set_tail_light_config(&mut self, config: TailLightConfig) -> Result<()> {
+    tracing::debug!("Setting tail light config: {:?}", config);
+
+    self.write_setting(Attribute::TailLight, config.into()).await
   }
 
+  /**
+   * Change only mode bits of tail light register, bits above mode (see TailLightConfig) are read and written back
+   */
+  pub async fn set_tail_light(&mut self, mode : TailLight) -> Result<()> {
+    tracing::debug!("Setting tail light: {:?}", mode);
+
+    let config = self.tail_light_config().await?;
+    self.set_tail_light_config(TailLightConfig { mode, ..config }).await
+  }
+
+  /**
+   * Change only enabled bit of cruise register, remaining bits (see CruiseConfig) are read and written back
+   */
   pub async fn set_cruise(&mut self, on : bool) -> Result<()> {
     tracing::debug!("Setting cruise enabled: {}", on);
 
-    let payload = if on {
-      vec![0x01, 0x00]
-    } else {
-      vec![0x00, 0x00]
-    };
+    let config = self.cruise_config().await?;
+    self.set_cruise_config(CruiseConfig { enabled: on, ..config }).await
+  }
+
+  /**
+   * Read zero start state. With zero start enabled throttle works from standstill, otherwise scooter
+   * needs to be kicked off first. State is stored as u16 flag in register 0x7E (0x0000 = off, 0x0001 = on),
+   * older firmwares don't have this register at all.
+   */
+  pub async fn is_zero_start_on(&mut self) -> Result<bool> {
+    tracing::debug!("Reading zero start state");
 
     self.send(&ScooterCommand {
       direction: Direction::MasterToMotor,
-      read_write: ReadWrite::Write,
-      attribute: Attribute::Cruise,
-      payload
+      read_write: ReadWrite::Read,
+      attribute: Attribute::ZeroStart,
+      payload: vec![0x02]
     }).await?;
 
-    Ok(())
+    let mut payload = self.read(2).await?;
+    payload.pop_head()?;
+
+    payload.pop_bool()
+  }
+
+  /**
+   * Enable or disable zero start, writes 0x0001 or 0x0000 to register 0x7E
+   */
+  pub async fn set_zero_start(&mut self, enabled : bool) -> Result<()> {
+    tracing::debug!("Setting zero start enabled: {}", enabled);
+
+    self.write_setting(Attribute::ZeroStart, enabled as u16).await
   }
 }
@@ -0,0 +1,213 @@
+use super::MiSession;
+use super::info::MotorInfo;
+use super::battery::BatteryInfo;
+
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Serialize, Serializer};
+
+/**
+ * Readings further apart than this are reported as gap, e.g. when reconnecting took a while
+ */
+const DEFAULT_MAX_GAP : Duration = Duration::from_secs(10);
+
+/**
+ * Combined snapshot of motor info, battery info and range, e.g. for dashboards and trip logs
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Telemetry {
+  #[serde(serialize_with = "serialize_timestamp")]
+  pub timestamp: DateTime<Local>,
+  pub battery_percent: u16,
+  pub speed_kmh: f32,
+  pub avg_speed_kmh: f32,
+  pub trip_m: u32,
+  pub total_m: u32,
+  /**
+   * Temperature in celsius
+   */
+  pub frame_temp: f32,
+  pub uptime_s: u64,
+  pub voltage: f32,
+  pub current: f32,
+  pub capacity: u16,
+  /**
+   * Temperature in celsius
+   */
+  pub batt_temp_1: f32,
+  pub batt_temp_2: f32,
+  /**
+   * Range reported by firmware, None when it could not be read (some clones don't answer to it)
+   */
+  pub range_km: Option<f32>,
+}
+
+fn serialize_timestamp<S: Serializer>(timestamp: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(&timestamp.to_rfc3339())
+}
+
+impl Telemetry {
+  /**
+   * Build snapshot from separate readings, timestamp is set to now
+   */
+  pub fn new(motor: &MotorInfo, battery: &BatteryInfo, range_km: Option<f32>) -> Self {
+    Self {
+      timestamp: Local::now(),
+      battery_percent: motor.battery_percent,
+      speed_kmh: motor.speed_kmh,
+      avg_speed_kmh: motor.speed_average_kmh,
+      trip_m: motor.trip_distance_m,
+      total_m: motor.total_distance_m,
+      frame_temp: motor.frame_temperature,
+      uptime_s: motor.uptime.as_secs(),
+      voltage: battery.voltage,
+      current: battery.current,
+      capacity: battery.capacity,
+      batt_temp_1: battery.temperature_1,
+      batt_temp_2: battery.temperature_2,
+      range_km,
+    }
+  }
+
+  pub fn csv_header() -> &'static str {
+    "timestamp,battery_percent,speed_kmh,avg_speed_kmh,trip_m,total_m,frame_temp_c,uptime_s,voltage_v,current_a,capacity_mah,batt_temp_1_c,batt_temp_2_c,range_km"
+  }
+
+  /**
+   * Row matching csv_header, range which was not read is left empty
+   */
+  pub fn to_csv_row(&self) -> String {
+    format!(
+      "{},{},{:.1},{:.1},{},{},{:.1},{},{:.2},{:.2},{},{:.1},{:.1},{}",
+      self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+      self.battery_percent,
+      self.speed_kmh,
+      self.avg_speed_kmh,
+      self.trip_m,
+      self.total_m,
+      self.frame_temp,
+      self.uptime_s,
+      self.voltage,
+      self.current,
+      self.capacity,
+      self.batt_temp_1,
+      self.batt_temp_2,
+      self.range_km.map(|range_km| format!("{:.1}", range_km)).unwrap_or_default()
+    )
+  }
+}
+
+/**
+ * How telemetry reading follows the previous one, see SessionContinuity
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Continuity {
+  /**
+   * First reading or reading right after previous one
+   */
+  Continuous,
+  /**
+   * Readings are missing between previous and this one (reconnect or slow read).
+   * distance_m is how far scooter moved meanwhile according to odometer
+   */
+  Gap { elapsed: Duration, distance_m: u32, reconnected: bool },
+  /**
+   * Odometer went back, so this is other scooter or controller was replaced / reset. Trip math over
+   * this point is not valid
+   */
+  OdometerReset { previous_m: u32, current_m: u32 },
+}
+
+impl Continuity {
+  /**
+   * Comment line to put into CSV log before reading, None for continuous readings.
+   * Lines start with #, most CSV readers skip them (e.g. pandas with comment='#')
+   */
+  pub fn csv_marker(&self) -> Option<String> {
+    match self {
+      Continuity::Continuous => None,
+      Continuity::Gap { elapsed, distance_m, reconnected } => Some(format!(
+        "# {} {}s, {}m travelled without readings",
+        if *reconnected { "reconnected after" } else { "gap of" },
+        elapsed.as_secs(),
+        distance_m
+      )),
+      Continuity::OdometerReset { previous_m, current_m } => Some(format!(
+        "# odometer reset from {}m to {}m", previous_m, current_m
+      )),
+    }
+  }
+}
+
+/**
+ * Track telemetry readings across reconnects, so logging tools can mark gaps instead of silently joining
+ * readings which are minutes apart. Feed it every reading and call reconnected when session was restored
+ */
+#[derive(Debug, Clone)]
+pub struct SessionContinuity {
+  max_gap: Duration,
+  last: Option<(DateTime<Local>, u32)>,
+  reconnected: bool,
+}
+
+impl Default for SessionContinuity {
+  fn default() -> Self {
+    Self::new(DEFAULT_MAX_GAP)
+  }
+}
+
+impl SessionContinuity {
+  pub fn new(max_gap: Duration) -> Self {
+    Self { max_gap, last: None, reconnected: false }
+  }
+
+  /**
+   * Next reading is reported as gap even when it comes quickly
+   */
+  pub fn reconnected(&mut self) {
+    self.reconnected = true;
+  }
+
+  pub fn update(&mut self, telemetry: &Telemetry) -> Continuity {
+    let reconnected = std::mem::take(&mut self.reconnected);
+    let last = self.last.replace((telemetry.timestamp, telemetry.total_m));
+
+    let Some((last_timestamp, last_total_m)) = last else {
+      return Continuity::Continuous
+    };
+
+    if telemetry.total_m < last_total_m {
+      return Continuity::OdometerReset { previous_m: last_total_m, current_m: telemetry.total_m }
+    }
+
+    let elapsed = (telemetry.timestamp - last_timestamp).to_std().unwrap_or_default();
+    if reconnected || elapsed > self.max_gap {
+      Continuity::Gap { elapsed, distance_m: telemetry.total_m - last_total_m, reconnected }
+    } else {
+      Continuity::Continuous
+    }
+  }
+}
+
+impl MiSession {
+  /**
+   * Read motor info, battery info and range in one go. Some clones don't answer to distance left,
+   * range is None for them instead of failing whole snapshot
+   */
+  pub async fn telemetry(&mut self) -> Result<Telemetry> {
+    tracing::debug!("Reading telemetry");
+
+    let motor = self.motor_info().await?;
+    let battery = self.battery_info().await?;
+    let range_km = match self.distance_left().await {
+      Ok(range_km) => Some(range_km),
+      Err(err) => {
+        tracing::warn!("Could not read distance left: {}", err);
+        None
+      }
+    };
+
+    Ok(Telemetry::new(&motor, &battery, range_km))
+  }
+}
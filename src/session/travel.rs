@@ -1,11 +1,114 @@
-use super::MiSession;
+use super::{MiSession, Payload};
 use super::commands::{ScooterCommand, Direction, Attribute, ReadWrite};
+use super::response::{Response, expect_attribute};
+use super::settings::SpeedMode;
 
+use std::time::Duration;
 use anyhow::Result;
+use serde::Serialize;
+
+/**
+ * Simple consumption model used for estimating range. Default values match stock M365
+ * (36V 7.8Ah pack), override them with MiSession::set_range_model for other scooters or riders
+ */
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeModel {
+  /**
+   * Usable battery energy when fully charged, in watt hours
+   */
+  pub battery_capacity_wh: f32,
+  /**
+   * Consumption in watt hours per kilometer
+   */
+  pub eco_wh_per_km: f32,
+  pub drive_wh_per_km: f32,
+  pub sport_wh_per_km: f32,
+}
+
+impl Default for RangeModel {
+  fn default() -> Self {
+    Self {
+      battery_capacity_wh: 280.0,
+      eco_wh_per_km: 10.0,
+      drive_wh_per_km: 12.5,
+      sport_wh_per_km: 15.5,
+    }
+  }
+}
+
+impl RangeModel {
+  pub fn consumption(&self, mode: SpeedMode) -> f32 {
+    match mode {
+      SpeedMode::Eco => self.eco_wh_per_km,
+      SpeedMode::Drive => self.drive_wh_per_km,
+      SpeedMode::Sport => self.sport_wh_per_km,
+    }
+  }
+
+  /**
+   * Kilometers left with battery charged in given percent (0 - 100)
+   */
+  pub fn range_km(&self, mode: SpeedMode, battery_percent: f32) -> f32 {
+    let consumption = self.consumption(mode);
+    if consumption <= 0.0 {
+      return 0.0
+    }
+
+    let energy_left = self.battery_capacity_wh * battery_percent.clamp(0.0, 100.0) / 100.0;
+    energy_left / consumption
+  }
+}
+
+/**
+ * Current trip counters from 0x3A: riding seconds (0x3A) and trip meters (0x3B), both unsigned 16 bit.
+ * Controller does not store maximum speed of trip, poll speed during ride for that
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TripSummary {
+  pub riding_time: Duration,
+  /**
+   * Distance is in meters
+   */
+  pub trip_distance_m: u32,
+}
+
+impl TryFrom<Payload> for TripSummary {
+  type Error = anyhow::Error;
+
+  fn try_from(payload: Payload) -> Result<Self, Self::Error> {
+    let mut payload = payload;
+    payload.pop_head()?;
+
+    let riding_time = Duration::from_secs(payload.pop_u16()? as u64);
+    let trip_distance_m = payload.pop_u16()? as u32;
+
+    Ok(TripSummary { riding_time, trip_distance_m })
+  }
+}
+
+impl Response for TripSummary {
+  fn parse(attribute: Attribute, payload: &[u8]) -> Result<Self> {
+    TripSummary::try_from(expect_attribute(&attribute, Attribute::TripInfo, payload)?)
+  }
+}
 
 impl MiSession {
   /**
-   * Get travel distance left in kilometers
+   * Read riding time and distance of current trip
+   */
+  pub async fn trip_summary(&mut self) -> Result<TripSummary> {
+    tracing::debug!("Reading trip summary");
+
+    self.request(&ScooterCommand {
+      direction: Direction::MasterToMotor,
+      read_write: ReadWrite::Read,
+      attribute: Attribute::TripInfo,
+      payload: vec![0x04]
+    }, 2).await
+  }
+
+  /**
+   * Get travel distance left in kilometers. Error means range was not read (e.g. timeout), don't show it as 0 km
    */
   pub async fn distance_left(&mut self) -> Result<f32> {
     tracing::debug!("Reading distance left");
@@ -30,7 +133,8 @@ impl MiSession {
   }
 
   /**
-   * Get current speed in kilometers per hour
+   * Get current speed in kilometers per hour. Reads only register 0xB5 (signed meters per hour, km/h * 1000,
+   * negative when pushed backwards), so it is cheaper than motor_info for speedometers polled many times a second
    */
   pub async fn speed(&mut self) -> Result<f32> {
     tracing::debug!("Reading speed");
@@ -76,4 +180,16 @@ impl MiSession {
 
     Ok(trip_distance)
   }
+
+  /**
+   * Estimate range in kilometers for speed mode using battery percentage and range model.
+   * Firmware's distance_left doesn't care about speed mode, so it is usually too optimistic in sport mode
+   */
+  pub async fn estimated_range(&mut self, mode: SpeedMode) -> Result<f32> {
+    let battery_percent = self.battery_percentage().await?;
+    let range = self.range_model().range_km(mode, battery_percent);
+    tracing::debug!("Estimated range in {:?}: {}km", mode, range);
+
+    Ok(range)
+  }
 }
@@ -0,0 +1,22 @@
+/*!
+ * Helpers shared by scanner, connection and session modules
+ */
+use std::future::Future;
+use std::time::Duration;
+use thiserror::Error;
+
+/**
+ * Operation did not finish in time. Every timeout of this crate is reported with this error,
+ * check for it with `err.is::<Timeout>()` on anyhow errors
+ */
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("No response in {0:?}")]
+pub struct Timeout(pub Duration);
+
+/**
+ * Run future for at most duration. Future is dropped when time runs out, so anything it was waiting for
+ * (e.g. late notification) has to be cleaned up by the caller
+ */
+pub async fn with_timeout<F: Future>(future: F, duration: Duration) -> Result<F::Output, Timeout> {
+  tokio::time::timeout(duration, future).await.map_err(|_| Timeout(duration))
+}
@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+use ninebot_ble::PassiveTelemetry;
+use uuid::Uuid;
+
+#[test]
+fn it_parses_battery_from_mibeacon() {
+    // frame control 0x0050 (mac + object), product 0x0A1C, counter 7, mac, battery object 0x100A = 63%
+    let frame = hex::decode("50001c0a07b2a13bdcb8c70a10013f").unwrap();
+    let mut service_data = HashMap::new();
+    service_data.insert(Uuid::parse_str("0000fe95-0000-1000-8000-00805f9b34fb").unwrap(), frame);
+
+    let telemetry = PassiveTelemetry::from_advertisement(&service_data, &HashMap::new()).unwrap();
+
+    assert_eq!(telemetry.product_id, 0x0A1C);
+    assert_eq!(telemetry.frame_counter, 7);
+    assert_eq!(telemetry.battery_percent, 63);
+}
+
+#[test]
+fn it_ignores_encrypted_mibeacon() {
+    let frame = hex::decode("58001c0a07b2a13bdcb8c70a10013f").unwrap();
+
+    assert_eq!(PassiveTelemetry::from_mibeacon(&frame), None);
+    assert_eq!(PassiveTelemetry::from_advertisement(&HashMap::new(), &HashMap::new()), None);
+}
@@ -19,3 +19,68 @@ fn it_calculates_did() {
     assert_eq!(24, did_ct.len());
     assert_eq!(12, token.len());
 }
+
+#[test]
+fn it_imports_token() {
+    let token = hex_literal::hex!("0102030405060708090a0b0c");
+
+    assert_eq!(mi_crypto::import_token(&token).unwrap(), token);
+    assert_eq!(mi_crypto::import_token(b"0102030405060708090a0b0c\n").unwrap(), token);
+    assert_eq!(mi_crypto::export_token(&token), token.to_vec());
+
+    let mut derived_key = [0xffu8; 64];
+    derived_key[0..12].copy_from_slice(&token);
+    assert_eq!(mi_crypto::import_token(&derived_key).unwrap(), token);
+}
+
+#[test]
+fn it_rejects_invalid_token() {
+    assert!(mi_crypto::import_token(&[0u8; 12]).is_err());
+    assert!(mi_crypto::import_token(&[1u8; 11]).is_err());
+    assert!(mi_crypto::import_token(b"not a token at all, really").is_err());
+}
+
+#[test]
+fn it_saves_and_loads_framed_token() {
+    let token: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+    let framed = mi_crypto::save_token_v1(&token);
+
+    assert_eq!(&framed[0..5], b"MITK\x01");
+    assert_eq!(mi_crypto::load_token_v1(&framed).unwrap(), token);
+    assert_eq!(mi_crypto::load_token_v1(&token).unwrap(), token);
+}
+
+#[test]
+fn it_rejects_unknown_token_file() {
+    assert!(mi_crypto::load_token_v1(b"MITK\x02\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c").is_err());
+    assert!(mi_crypto::load_token_v1(b"MITK\x01\x01\x02").is_err());
+    assert!(mi_crypto::load_token_v1(&[0u8; 17]).is_err());
+}
+
+#[test]
+fn it_keeps_tokens_by_address() {
+    let first = ninebot_ble::parse_mac("C7:B8:DC:3B:A1:B2").unwrap();
+    let second = ninebot_ble::parse_mac("AA:BB:CC:DD:EE:FF").unwrap();
+    let token: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+    let mut store = mi_crypto::TokenStore::new();
+    assert_eq!(store.put(first, token), None);
+    store.put(second, [0xAB; 12]);
+
+    let text = store.to_string();
+    assert!(text.contains("\"C7:B8:DC:3B:A1:B2\" = \"0102030405060708090a0b0c\""));
+
+    let mut loaded = mi_crypto::TokenStore::parse(&format!("# scooters\n\n{}", text)).unwrap();
+    assert_eq!(loaded, store);
+    assert_eq!(loaded.get(&first), Some(&token));
+    assert_eq!(loaded.remove(&second), Some([0xAB; 12]));
+    assert_eq!(loaded.get(&second), None);
+    assert_eq!(loaded.len(), 1);
+}
+
+#[test]
+fn it_rejects_invalid_token_store() {
+    assert!(mi_crypto::TokenStore::parse("\"C7:B8:DC:3B:A1:B2\"").is_err());
+    assert!(mi_crypto::TokenStore::parse("\"not a mac\" = \"0102030405060708090a0b0c\"").is_err());
+    assert!(mi_crypto::TokenStore::parse("\"C7:B8:DC:3B:A1:B2\" = \"0102\"").is_err());
+}
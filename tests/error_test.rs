@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Context};
+use ninebot_ble::session::{Attribute, SessionError};
+use ninebot_ble::util::Timeout;
+use ninebot_ble::Error;
+use std::time::Duration;
+
+#[test]
+fn it_keeps_typed_errors_from_anyhow() {
+    let err: Error = anyhow::Error::from(Timeout(Duration::from_secs(5))).into();
+    assert!(matches!(err, Error::Timeout(Timeout(duration)) if duration == Duration::from_secs(5)));
+
+    let write = SessionError::WriteNotApplied { attribute: Attribute::Cruise, written: 1, read: 0 };
+    let err: Error = anyhow::Error::from(write.clone()).context("Setting cruise").into();
+    assert!(matches!(err, Error::Session(session) if session == write));
+}
+
+#[test]
+fn it_wraps_untyped_errors_as_other() {
+    let err: Error = anyhow!("Response is too short: 2 bytes").into();
+
+    assert!(matches!(err, Error::Other(_)));
+    assert_eq!(err.to_string(), "Response is too short: 2 bytes");
+}
+
+#[test]
+fn it_converts_with_question_mark() {
+    fn read() -> ninebot_ble::Result<u16> {
+        let value: anyhow::Result<u16> = Err(Timeout(Duration::from_millis(10))).context("Reading speed");
+        Ok(value?)
+    }
+
+    assert!(matches!(read(), Err(Error::Timeout(_))));
+}
+
+#[test]
+fn it_keeps_not_authenticated() {
+    let err: Error = anyhow::Error::from(SessionError::NotAuthenticated).into();
+
+    assert!(matches!(err, Error::Session(SessionError::NotAuthenticated)));
+    assert!(err.to_string().contains("resume"));
+}
+
+#[test]
+fn it_tells_link_errors_apart() {
+    let timeout: Error = anyhow::Error::from(Timeout(Duration::from_secs(5))).into();
+    let not_authenticated: Error = anyhow::Error::from(SessionError::NotAuthenticated).into();
+    let write: Error = SessionError::WriteNotApplied { attribute: Attribute::Cruise, written: 1, read: 0 }.into();
+    let malformed: Error = anyhow!("Response is too short: 2 bytes").into();
+
+    assert!(timeout.is_link_error());
+    assert!(not_authenticated.is_link_error());
+    assert!(!write.is_link_error());
+    assert!(!malformed.is_link_error());
+}
+
+#[test]
+fn it_keeps_context_chain_of_other_errors() {
+    let err: Error = anyhow!("Response is too short: 2 bytes").context("Raw response [55, AA]").into();
+
+    assert_eq!(err.to_string(), "Raw response [55, AA]");
+    let source = std::error::Error::source(&err).expect("parse error under context");
+    assert_eq!(source.to_string(), "Response is too short: 2 bytes");
+}
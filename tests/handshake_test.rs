@@ -0,0 +1,147 @@
+use ninebot_ble::consts::MiCommands;
+use ninebot_ble::mi_crypto::{self, Expect, MiAuthError, MiAuthStateMachine, OutgoingCmd};
+use p256::{ecdh::EphemeralSecret, EncodedPoint};
+use rand_core::OsRng;
+
+const TOKEN: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+const RAND_KEY: [u8; 16] = [0x11; 16];
+const REMOTE_KEY: [u8; 16] = [0x22; 16];
+
+fn login_until_remote_info() -> MiAuthStateMachine {
+    let mut machine = MiAuthStateMachine::login_with_rand_key(&TOKEN, RAND_KEY);
+
+    assert!(matches!(
+        machine.start(),
+        OutgoingCmd::Command { command: MiCommands::CMD_LOGIN, expect: Expect::Nothing, .. }
+    ));
+    assert!(matches!(
+        machine.step(&[]).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_SEND_KEY, expect: Expect::Notification, .. })
+    ));
+    match machine.step(&MiCommands::RCV_RDY.to_bytes()).unwrap() {
+        Some(OutgoingCmd::Parcel { data, .. }) => assert_eq!(data, RAND_KEY),
+        other => panic!("Expected rand key parcel, got {:?}", other),
+    }
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_OK.to_bytes()).unwrap(),
+        Some(OutgoingCmd::Wait { expect: Expect::Parcel, .. })
+    ));
+    assert!(matches!(
+        machine.step(&REMOTE_KEY).unwrap(),
+        Some(OutgoingCmd::Wait { expect: Expect::Parcel, .. })
+    ));
+
+    machine
+}
+
+#[test]
+fn it_drives_login_handshake() {
+    let (info, remote_info, _) =
+        mi_crypto::calc_login_did(&mut RAND_KEY.clone(), &mut REMOTE_KEY.clone(), &TOKEN);
+    let mut machine = login_until_remote_info();
+
+    assert!(matches!(
+        machine.step(&remote_info).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_SEND_INFO, .. })
+    ));
+    match machine.step(&MiCommands::RCV_RDY.to_bytes()).unwrap() {
+        Some(OutgoingCmd::Parcel { data, .. }) => assert_eq!(data, info),
+        other => panic!("Expected info parcel, got {:?}", other),
+    }
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_OK.to_bytes()).unwrap(),
+        Some(OutgoingCmd::Wait { expect: Expect::Notification, .. })
+    ));
+    assert!(machine.keys().is_none());
+
+    assert!(machine.step(&MiCommands::RCV_LOGIN_OK.to_bytes()).unwrap().is_none());
+    assert!(machine.is_finished());
+    assert!(machine.keys().is_some());
+    assert!(matches!(machine.step(&[]), Err(MiAuthError::Finished)));
+}
+
+#[test]
+fn it_rejects_invalid_remote_info() {
+    let mut machine = login_until_remote_info();
+
+    assert!(matches!(machine.step(&[0u8; 32]), Err(MiAuthError::InvalidDid)));
+}
+
+#[test]
+fn it_fails_login_on_error_response() {
+    let (_, remote_info, _) =
+        mi_crypto::calc_login_did(&mut RAND_KEY.clone(), &mut REMOTE_KEY.clone(), &TOKEN);
+    let mut machine = login_until_remote_info();
+
+    machine.step(&remote_info).unwrap();
+    machine.step(&MiCommands::RCV_RDY.to_bytes()).unwrap();
+    machine.step(&MiCommands::RCV_OK.to_bytes()).unwrap();
+
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_LOGIN_ERR.to_bytes()),
+        Err(MiAuthError::LoginFailed)
+    ));
+}
+
+#[test]
+fn it_drives_registration_handshake() {
+    let scooter_secret = EphemeralSecret::random(&mut OsRng);
+    let scooter_public_key = EncodedPoint::from(scooter_secret.public_key());
+    let remote_info = [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x62, 0x6c, 0x74, 0x2e, 0x33, 0x2e, 0x31, 0x36, 0x33, 0x39,
+        0x34, 0x74, 0x33, 0x67, 0x34, 0x6c, 0x63, 0x30, 0x30,
+    ];
+    let mut machine = MiAuthStateMachine::register();
+
+    assert!(matches!(
+        machine.start(),
+        OutgoingCmd::Command { command: MiCommands::CMD_GET_INFO, expect: Expect::Parcel, .. }
+    ));
+    assert!(machine.device_id().is_none());
+    assert!(matches!(
+        machine.step(&remote_info).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_SET_KEY, expect: Expect::Nothing, .. })
+    ));
+    assert_eq!(machine.device_id().as_deref(), Some("blt.3.16394t3g4lc00"));
+    assert!(matches!(
+        machine.step(&[]).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_SEND_DATA, .. })
+    ));
+    match machine.step(&MiCommands::RCV_RDY.to_bytes()).unwrap() {
+        Some(OutgoingCmd::Parcel { data, .. }) => assert_eq!(data.len(), 64),
+        other => panic!("Expected public key parcel, got {:?}", other),
+    }
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_OK.to_bytes()).unwrap(),
+        Some(OutgoingCmd::Wait { expect: Expect::Parcel, .. })
+    ));
+    // scooter sends public key without 0x04 prefix
+    assert!(matches!(
+        machine.step(&scooter_public_key.as_bytes()[1..]).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_SEND_DID, .. })
+    ));
+    match machine.step(&MiCommands::RCV_RDY.to_bytes()).unwrap() {
+        Some(OutgoingCmd::Parcel { data, .. }) => assert_eq!(data.len(), 24),
+        other => panic!("Expected did parcel, got {:?}", other),
+    }
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_OK.to_bytes()).unwrap(),
+        Some(OutgoingCmd::Command { command: MiCommands::CMD_AUTH, .. })
+    ));
+    assert!(machine.token().is_none());
+
+    assert!(machine.step(&MiCommands::RCV_AUTH_OK.to_bytes()).unwrap().is_none());
+    assert!(machine.token().is_some());
+}
+
+#[test]
+fn it_reports_unexpected_response() {
+    let mut machine = MiAuthStateMachine::login_with_rand_key(&TOKEN, RAND_KEY);
+    machine.start();
+    machine.step(&[]).unwrap();
+
+    assert!(matches!(
+        machine.step(&MiCommands::RCV_OK.to_bytes()),
+        Err(MiAuthError::UnexpectedResponse { expected: "RCV_RDY", .. })
+    ));
+}
@@ -0,0 +1,60 @@
+use ninebot_ble::session::{DeviceKind, ScooterModel};
+use uuid::Uuid;
+
+const XIAOMI_SERVICE: Uuid = Uuid::from_u128(0x0000fe95_0000_1000_8000_00805f9b34fb);
+const NUS_SERVICE: Uuid = Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+
+#[test]
+fn it_identifies_genuine_xiaomi() {
+    let kind = DeviceKind::classify(&[XIAOMI_SERVICE, NUS_SERVICE], true, Some("26354/00467353"));
+
+    assert_eq!(kind, DeviceKind::GenuineXiaomi);
+}
+
+#[test]
+fn it_identifies_clone_by_serial() {
+    assert_eq!(DeviceKind::classify(&[XIAOMI_SERVICE], true, Some("M365CLONE0001")), DeviceKind::Clone);
+    assert_eq!(DeviceKind::classify(&[XIAOMI_SERVICE], true, None), DeviceKind::Clone);
+    assert_eq!(DeviceKind::classify(&[NUS_SERVICE], false, Some("00000/00000000")), DeviceKind::Clone);
+}
+
+#[test]
+fn it_needs_miauth_for_genuine() {
+    let kind = DeviceKind::classify(&[XIAOMI_SERVICE], false, Some("26354/00467353"));
+
+    assert_eq!(kind, DeviceKind::Clone);
+}
+
+#[test]
+fn it_identifies_ninebot_es() {
+    let kind = DeviceKind::classify(&[NUS_SERVICE], false, Some("N2GSD1234C5678"));
+
+    assert_eq!(kind, DeviceKind::NinebotEs);
+}
+
+#[test]
+fn it_does_not_guess_without_data() {
+    assert_eq!(DeviceKind::classify(&[], false, None), DeviceKind::Unknown);
+    assert_eq!(DeviceKind::classify(&[NUS_SERVICE], false, Some("  ")), DeviceKind::Unknown);
+}
+
+#[test]
+fn it_rejects_only_probed_unsupported_attributes() {
+    use ninebot_ble::session::{Attribute, FirmwareCompat};
+
+    let compat = FirmwareCompat { version: "1.3.4".to_owned(), unsupported: vec![Attribute::ZeroStart] };
+
+    assert!(!compat.supports(&Attribute::ZeroStart));
+    assert!(compat.supports(&Attribute::MotorInfo));
+    assert!(compat.supports(&Attribute::Raw(0x7E)));
+}
+
+#[test]
+fn it_decodes_model_from_serial_prefix() {
+    assert_eq!(ScooterModel::from_serial("16132/00095292"), ScooterModel::M365);
+    assert_eq!(ScooterModel::from_serial(" 16132/00012345 "), ScooterModel::M365);
+    // prefix of this unit is not confirmed, so model is not guessed
+    assert_eq!(ScooterModel::from_serial("26354/00467353"), ScooterModel::Unknown);
+    assert_eq!(ScooterModel::from_serial("16132"), ScooterModel::Unknown);
+    assert_eq!(ScooterModel::from_serial("N2GSD1234C5678"), ScooterModel::Unknown);
+}
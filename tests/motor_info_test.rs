@@ -1,6 +1,9 @@
 use hex_literal::hex;
 
-use ninebot_ble::session::{BatteryInfo, MotorInfo, Payload};
+use ninebot_ble::session::{
+    Attribute, BatteryInfo, Continuity, EventTracker, MotorInfo, Payload, Response, ScooterError,
+    ScooterTelemetryEvent, SessionContinuity, StatusFlags, Telemetry, TripCounter, TripSummary,
+};
 use std::time::Duration;
 
 #[test]
@@ -19,6 +22,36 @@ fn it_transform_payload_into_motor_info() {
     assert_eq!(motor_info.frame_temperature, 25.0);
 }
 
+#[test]
+fn it_reads_trip_distance_above_i16() {
+    // same frame as above with trip meters set to 0x9c40 = 40000m
+    let bytes =
+        hex!("2301b00000000000080000400000000000e3ed1300409c5800fa000000000000000000676598f0");
+    let payload = Payload::from(&bytes[0..]);
+    let motor_info = MotorInfo::try_from(payload).unwrap();
+
+    assert_eq!(motor_info.trip_distance_m, 40000);
+}
+
+#[test]
+fn it_counts_trip_rollover() {
+    let mut trip = TripCounter::new();
+
+    assert_eq!(trip.update(65000), 65000);
+    assert_eq!(trip.update(65535), 65535);
+    assert_eq!(trip.update(200), 65736);
+    assert_eq!(trip.update(1500), 67036);
+}
+
+#[test]
+fn it_treats_other_drops_as_trip_reset() {
+    let mut trip = TripCounter::new();
+
+    assert_eq!(trip.update(65200), 65200);
+    assert_eq!(trip.update(100), 65636);
+    assert_eq!(trip.update(5), 5);
+}
+
 #[test]
 fn it_transform_payload_into_battery_info() {
     let bytes = hex!("250131f91c3f0001005c0e2d2d1178f518");
@@ -29,6 +62,125 @@ fn it_transform_payload_into_battery_info() {
     assert_eq!(battery.percent, 63);
     assert_eq!(battery.current, 0.01);
     assert_eq!(battery.voltage, 36.76);
-    assert_eq!(battery.temperature_1, 45);
-    assert_eq!(battery.temperature_2, 45);
+    assert_eq!(battery.temperature_1, 25.0);
+    assert_eq!(battery.temperature_2, 25.0);
+}
+
+#[test]
+fn it_decodes_battery_temperatures_below_zero() {
+    // 0x0a and 0x14 are -10°C and 0°C after BMS offset of 20
+    let bytes = hex!("250131f91c3f0001005c0e0a141178f518");
+    let battery = BatteryInfo::try_from(Payload::from(&bytes[0..])).unwrap();
+
+    assert_eq!(battery.temperature_1, -10.0);
+    assert_eq!(battery.temperature_2, 0.0);
+}
+
+#[test]
+fn it_transform_payload_into_status_flags() {
+    let bytes =
+        hex!("2301b00000000000080000400000000000e3ed130000005800fa000000000000000000676598f0");
+    let payload = Payload::from(&bytes[0..]);
+    let status = StatusFlags::try_from(payload).unwrap();
+
+    assert_eq!(status, StatusFlags::ACTIVATED);
+}
+
+#[test]
+fn it_sets_derived_status_flags() {
+    let bytes = hex!("2301b003000000020200003d003a1f");
+    let payload = Payload::from(&bytes[0..]);
+    let status = StatusFlags::try_from(payload).unwrap();
+
+    assert!(status.contains(StatusFlags::LOCKED | StatusFlags::CHARGING | StatusFlags::ERROR | StatusFlags::RIDING));
+    assert!(!status.contains(StatusFlags::WARNING));
+}
+
+#[test]
+fn it_combines_motor_and_battery_into_telemetry() {
+    let motor_bytes =
+        hex!("2301b00000000000080000400000000000e3ed130000005800fa000000000000000000676598f0");
+    let battery_bytes = hex!("250131f91c3f0001005c0e2d2d1178f518");
+    let motor = MotorInfo::try_from(Payload::from(&motor_bytes[0..])).unwrap();
+    let battery = BatteryInfo::try_from(Payload::from(&battery_bytes[0..])).unwrap();
+
+    let telemetry = Telemetry::new(&motor, &battery, Some(12.5));
+
+    assert_eq!(telemetry.battery_percent, 64);
+    assert_eq!(telemetry.total_m, 1306083);
+    assert_eq!(telemetry.voltage, 36.76);
+    assert_eq!(telemetry.capacity, 7417);
+    assert_eq!(telemetry.range_km, Some(12.5));
+
+    let row = telemetry.to_csv_row();
+    assert_eq!(row.split(',').count(), Telemetry::csv_header().split(',').count());
+    assert!(row.ends_with(",7417,25.0,25.0,12.5"));
+
+    let unread = Telemetry::new(&motor, &battery, None);
+    assert!(unread.to_csv_row().ends_with(",7417,25.0,25.0,"));
+}
+
+#[test]
+fn it_transform_payload_into_trip_summary() {
+    // doc/protocol.md: 55aa 06 2301 3a 7b02 0a00 14ff, 635 seconds and 10 meters
+    let bytes = hex!("23013a7b020a00");
+    let summary = TripSummary::try_from(Payload::from(&bytes[0..])).unwrap();
+
+    assert_eq!(summary.riding_time, Duration::from_secs(635));
+    assert_eq!(summary.trip_distance_m, 10);
+}
+
+#[test]
+fn it_marks_gaps_and_odometer_resets_in_telemetry() {
+    let motor_bytes =
+        hex!("2301b00000000000080000400000000000e3ed130000005800fa000000000000000000676598f0");
+    let battery_bytes = hex!("250131f91c3f0001005c0e2d2d1178f518");
+    let motor = MotorInfo::try_from(Payload::from(&motor_bytes[0..])).unwrap();
+    let battery = BatteryInfo::try_from(Payload::from(&battery_bytes[0..])).unwrap();
+    let first = Telemetry::new(&motor, &battery, None);
+    let mut continuity = SessionContinuity::new(Duration::from_secs(10));
+
+    assert_eq!(continuity.update(&first), Continuity::Continuous);
+
+    let mut next = first.clone();
+    next.timestamp = first.timestamp + chrono::Duration::seconds(1);
+    next.total_m = first.total_m + 5;
+    assert_eq!(continuity.update(&next), Continuity::Continuous);
+
+    let mut after_reconnect = next.clone();
+    after_reconnect.timestamp = next.timestamp + chrono::Duration::seconds(30);
+    after_reconnect.total_m = next.total_m + 120;
+    continuity.reconnected();
+    let gap = continuity.update(&after_reconnect);
+    assert_eq!(gap, Continuity::Gap { elapsed: Duration::from_secs(30), distance_m: 120, reconnected: true });
+    assert_eq!(gap.csv_marker().unwrap(), "# reconnected after 30s, 120m travelled without readings");
+
+    let mut other_scooter = after_reconnect.clone();
+    other_scooter.timestamp = after_reconnect.timestamp + chrono::Duration::seconds(1);
+    other_scooter.total_m = 100;
+    assert_eq!(
+        continuity.update(&other_scooter),
+        Continuity::OdometerReset { previous_m: first.total_m + 125, current_m: 100 }
+    );
+}
+
+#[test]
+fn it_reports_only_changes_as_events() {
+    let bytes =
+        hex!("2301b00000000000080000400000000000e3ed130000005800fa000000000000000000676598f0");
+    let mut motor_info = MotorInfo::try_from(Payload::from(&bytes[0..])).unwrap();
+    let mut tracker = EventTracker::new();
+
+    assert_eq!(
+        tracker.update(&motor_info, 0),
+        vec![ScooterTelemetryEvent::SpeedUpdate(0.0), ScooterTelemetryEvent::BatteryUpdate(64)]
+    );
+    assert_eq!(tracker.update(&motor_info, 0), vec![]);
+
+    motor_info.speed_kmh = 12.5;
+    assert_eq!(
+        tracker.update(&motor_info, 14),
+        vec![ScooterTelemetryEvent::SpeedUpdate(12.5), ScooterTelemetryEvent::Fault(ScooterError { code: 14 })]
+    );
+    assert_eq!(tracker.update(&motor_info, 0), vec![ScooterTelemetryEvent::FaultCleared]);
 }
@@ -0,0 +1,53 @@
+use hex_literal::hex;
+use ninebot_ble::ninebot::{Address, NinebotCommand, NinebotFrame};
+use ninebot_ble::protocol::ninebot_checksum;
+use ninebot_ble::protocol_core::Attribute;
+use ninebot_ble::session::{BatteryInfo, Response};
+
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x5A, 0xA5];
+    bytes.extend_from_slice(body);
+    bytes.extend_from_slice(&ninebot_checksum(body).to_le_bytes());
+    bytes
+}
+
+#[test]
+fn it_encodes_ninebot_read() {
+    let cmd = NinebotCommand::read(Attribute::BatteryPercent, 2);
+
+    assert_eq!(cmd.destination, Address::Bms);
+    assert_eq!(cmd.as_bytes(), hex!("5aa5013e2201320269ff"));
+    assert_eq!(NinebotCommand::read(Attribute::FirmwareVersion, 2).as_bytes(), frame(&hex!("013e20011a02")));
+}
+
+#[test]
+fn it_decodes_ninebot_battery_info() {
+    // capacity 5000mAh, 80%, -1.5A, 40.00V, 25 and 26 celsius
+    let response = frame(&hex!("0a223e0431 8813 5000 6aff a00f 2d2e"));
+    let frame = NinebotFrame::parse(&response).unwrap();
+    frame.expect_response(&NinebotCommand::read(Attribute::BatteryInfo, 0x0A)).unwrap();
+
+    let info = BatteryInfo::parse(Attribute::BatteryInfo, &frame.response_bytes()).unwrap();
+
+    assert_eq!(info.capacity, 5000);
+    assert_eq!(info.percent, 80);
+    assert_eq!(info.current, -1.5);
+    assert_eq!(info.voltage, 40.0);
+    assert_eq!(info.temperature_1, 25.0);
+    assert_eq!(info.temperature_2, 26.0);
+}
+
+#[test]
+fn it_rejects_invalid_ninebot_frames() {
+    let mut response = frame(&hex!("02203e041a5501"));
+    assert!(NinebotFrame::parse(&response).is_ok());
+
+    // answer from ESC does not match read sent to BMS
+    let frame = NinebotFrame::parse(&response).unwrap();
+    assert!(frame.expect_response(&NinebotCommand::read(Attribute::BatteryPercent, 2)).is_err());
+
+    let last = response.len() - 1;
+    response[last] ^= 0xFF;
+    assert!(NinebotFrame::parse(&response).is_err());
+    assert!(NinebotFrame::parse(&hex!("55aa0320011a02bfff")).is_err());
+}
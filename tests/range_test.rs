@@ -0,0 +1,30 @@
+use ninebot_ble::session::{RangeModel, SpeedMode, Unit};
+
+#[test]
+fn it_estimates_range_for_speed_mode() {
+    let model = RangeModel::default();
+
+    assert_eq!(model.range_km(SpeedMode::Eco, 100.0), 28.0);
+    assert_eq!(model.range_km(SpeedMode::Eco, 50.0), 14.0);
+    assert!(model.range_km(SpeedMode::Sport, 50.0) < model.range_km(SpeedMode::Drive, 50.0));
+}
+
+#[test]
+fn it_uses_custom_range_model() {
+    let model = RangeModel {
+        battery_capacity_wh: 474.0,
+        eco_wh_per_km: 10.0,
+        drive_wh_per_km: 12.0,
+        sport_wh_per_km: 15.0,
+    };
+
+    assert_eq!(model.range_km(SpeedMode::Sport, 100.0), 31.6);
+    assert_eq!(model.range_km(SpeedMode::Drive, 120.0), 39.5);
+    assert_eq!(model.range_km(SpeedMode::Drive, -5.0), 0.0);
+}
+
+#[test]
+fn it_converts_metric_values_to_unit() {
+    assert_eq!(Unit::Km.from_km(25.0), 25.0);
+    assert!((Unit::Miles.from_km(25.0) - 15.534).abs() < 0.001);
+}
@@ -0,0 +1,52 @@
+use ninebot_ble::protocol::replay::{Capture, ReplayError};
+use ninebot_ble::protocol::Protocol;
+
+const FIRMWARE_CAPTURE: &str = "
+# M365 clone, firmware version
+> [55, AA, 03, 20, 01, 1A, 02, BF, FF]
+< [55, AA, 04, 23]
+< 01 1A 34 01 88 FF
+> 55aa0320011a02bfff
+< 55aa042301
+";
+
+#[test]
+fn it_parses_capture() {
+    let capture = Capture::parse(FIRMWARE_CAPTURE).unwrap();
+
+    assert_eq!(capture.exchanges.len(), 2);
+    assert_eq!(capture.exchanges[0].request, vec![0x55, 0xAA, 0x03, 0x20, 0x01, 0x1A, 0x02, 0xBF, 0xFF]);
+    assert_eq!(capture.exchanges[0].notifications.len(), 2);
+    assert_eq!(capture.exchanges[1].request, capture.exchanges[0].request);
+}
+
+#[test]
+fn it_replays_captured_responses() {
+    let capture = Capture::parse(FIRMWARE_CAPTURE).unwrap();
+    let mut player = capture.player();
+    let request = capture.exchanges[0].request.clone();
+
+    let frames = player.respond(&request).unwrap();
+    assert_eq!(frames, vec![vec![0x55, 0xAA, 0x04, 0x23, 0x01, 0x1A, 0x34, 0x01, 0x88, 0xFF]]);
+    assert_eq!(Protocol::detect(&frames[0]), Some(Protocol::Xiaomi));
+
+    // scooter stopped answering in the middle of frame
+    let frames = player.respond(&request).unwrap();
+    assert_eq!(Protocol::detect(&frames[0]), None);
+    assert!(player.is_finished());
+    assert!(matches!(player.respond(&request), Err(ReplayError::Exhausted(_))));
+}
+
+#[test]
+fn it_rejects_unexpected_request() {
+    let capture = Capture::parse(FIRMWARE_CAPTURE).unwrap();
+
+    assert!(matches!(capture.player().respond(&[0x55, 0xAA]), Err(ReplayError::UnexpectedRequest { .. })));
+}
+
+#[test]
+fn it_rejects_invalid_capture() {
+    assert!(matches!(Capture::parse("< 55 AA"), Err(ReplayError::InvalidLine { line: 1, .. })));
+    assert!(matches!(Capture::parse("> 55 AA\n? 01"), Err(ReplayError::InvalidLine { line: 2, .. })));
+    assert!(matches!(Capture::parse("> [55, GG]"), Err(ReplayError::InvalidLine { line: 1, .. })));
+}
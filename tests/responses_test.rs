@@ -1,4 +1,5 @@
 use hex_literal::hex;
+use ninebot_ble::session::{Attribute, BatteryInfo, CruiseConfig, Kers, MotorInfo, Response, SupplementaryInfo, TailLight, TailLightConfig};
 
 #[test]
 fn it_guess_what_distance_is_left() {
@@ -7,3 +8,48 @@ fn it_guess_what_distance_is_left() {
     let distance_left_meters = u16::from_le_bytes(distance_bytes);
     assert_eq!(distance_left_meters, 2610);
 }
+
+#[test]
+fn it_parses_response_for_sent_attribute() {
+    let bytes = hex!("250131f91c3f0001005c0e2d2d1178f518");
+    let battery = BatteryInfo::parse(Attribute::BatteryInfo, &bytes).unwrap();
+
+    assert_eq!(battery.capacity, 7417);
+    assert_eq!(battery.voltage, 36.76);
+}
+
+#[test]
+fn it_rejects_response_for_other_attribute() {
+    let bytes = hex!("250131f91c3f0001005c0e2d2d1178f518");
+
+    assert!(MotorInfo::parse(Attribute::BatteryInfo, &bytes).is_err());
+    assert!(MotorInfo::parse(Attribute::MotorInfo, &bytes).is_err());
+}
+
+#[test]
+fn it_parses_supplementary_info() {
+    let bytes = hex!("23017b010001000200");
+    let info = SupplementaryInfo::parse(Attribute::Supplementary, &bytes).unwrap();
+
+    assert!(matches!(info.kers, Kers::Medium));
+    assert!(info.is_cruise);
+    assert!(matches!(info.tail_light, TailLight::Always));
+}
+
+#[test]
+fn it_keeps_reserved_cruise_bits() {
+    let config = CruiseConfig::from(0x0105);
+
+    assert!(config.enabled);
+    assert_eq!(config.reserved, 0x0104);
+    assert_eq!(u16::from(CruiseConfig { enabled: false, ..config }), 0x0104);
+}
+
+#[test]
+fn it_keeps_reserved_tail_light_bits() {
+    let config = TailLightConfig::from(0x0102);
+
+    assert_eq!(config.mode, TailLight::Always);
+    assert_eq!(config.reserved, 0x0100);
+    assert_eq!(u16::from(TailLightConfig { mode: TailLight::OnBrake, ..config }), 0x0101);
+}
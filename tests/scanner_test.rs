@@ -0,0 +1,18 @@
+use ninebot_ble::parse_mac;
+
+#[test]
+fn it_parses_mac_in_multiple_formats() {
+    let expected = parse_mac("C7:B8:DC:3B:A1:B2").unwrap();
+
+    assert_eq!(parse_mac("C7-B8-DC-3B-A1-B2").unwrap(), expected);
+    assert_eq!(parse_mac("c7b8dc3ba1b2").unwrap(), expected);
+    assert_eq!(parse_mac(" C7:B8:DC:3B:A1:B2\n").unwrap(), expected);
+    assert_eq!(expected.to_string(), "C7:B8:DC:3B:A1:B2");
+}
+
+#[test]
+fn it_rejects_invalid_mac() {
+    assert!(parse_mac("C7:B8:DC:3B:A1").is_err());
+    assert!(parse_mac("C7:B8:DC:3B:A1:ZZ").is_err());
+    assert!(parse_mac("").is_err());
+}
@@ -1,5 +1,7 @@
 use hex_literal::hex;
-use ninebot_ble::mi_crypto::{crc16, decrypt_uart, encrypt_uart, EncryptionKey};
+use ninebot_ble::mi_crypto::{crc16, decrypt_uart, decrypt_uart_into, encrypt_uart, encrypt_uart_into, EncryptionKey};
+use ninebot_ble::protocol::{ninebot_checksum, xiaomi_checksum, FrameAssembler, Protocol};
+use ninebot_ble::protocol_core::{Attribute, Direction, ReadWrite, ScooterCommand};
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 
@@ -11,6 +13,92 @@ fn it_crc16() {
     assert_eq!(crc, hex!("23fe"));
 }
 
+#[test]
+fn it_calculates_xiaomi_checksum() {
+    // Get version frame from raw_probe: 55 AA 03 20 01 1A 02 BF FF
+    assert_eq!(xiaomi_checksum(&[0x03, 0x20, 0x01, 0x1A, 0x02]), 0xFFBF);
+    assert_eq!(xiaomi_checksum(&[0x03, 0x20, 0x01, 0x1A, 0x02]).to_le_bytes(), hex!("bfff"));
+    // Battery info from doc/protocol.md: 55 AA 03 22 01 31 0A 9E FF
+    assert_eq!(xiaomi_checksum(&hex!("032201310a")), 0xFF9E);
+    assert_eq!(xiaomi_checksum(&[]), 0xFFFF);
+}
+
+#[test]
+fn it_encodes_command_with_protocol_core() {
+    let cmd = ScooterCommand {
+        direction: Direction::MasterToMotor,
+        read_write: ReadWrite::Read,
+        attribute: Attribute::FirmwareVersion,
+        payload: vec![0x02],
+    };
+    let body = cmd.as_bytes();
+
+    assert_eq!(body, hex!("0320011a02"));
+    assert_eq!(Protocol::Xiaomi.checksum(&body).to_le_bytes(), hex!("bfff"));
+}
+
+#[test]
+fn it_matches_crc16_for_long_frames() {
+    // Cell voltages response from doc/protocol.md: 55 AA 20 25 01 40 ... 75 FE
+    let bytes = hex!("20 25 01 40 0210 0a10 0b10 0910 0610 0d10 0e10 0d10 0f10 0710 00000000000000000000");
+
+    assert_eq!(crc16(&bytes), hex!("75fe"));
+    assert_eq!(xiaomi_checksum(&bytes), 0xFE75);
+}
+
+#[test]
+fn it_calculates_ninebot_checksum() {
+    assert_eq!(ninebot_checksum(&hex!("01 3e 20 01 b0 20")), 0xFECF);
+    assert_eq!(Protocol::Ninebot.checksum(&hex!("032201310a")), Protocol::Xiaomi.checksum(&hex!("032201310a")));
+}
+
+#[test]
+fn it_detects_protocol_from_response() {
+    // Battery percent response, 64%
+    assert_eq!(Protocol::detect(&hex!("55 aa 04 23 01 32 40 00 65 ff")), Some(Protocol::Xiaomi));
+    assert_eq!(Protocol::detect(&hex!("5a a5 02 23 3e 01 32 40 00 29 ff")), Some(Protocol::Ninebot));
+}
+
+#[test]
+fn it_does_not_detect_broken_frames() {
+    // wrong checksum
+    assert_eq!(Protocol::detect(&hex!("55 aa 04 23 01 32 40 00 66 ff")), None);
+    // truncated
+    assert_eq!(Protocol::detect(&hex!("5a a5 02 23 3e 01 32 40")), None);
+    // encrypted
+    assert_eq!(Protocol::detect(&hex!("55ab03000016b2eddb0b680532a988c4f2dbf9")), None);
+    assert_eq!(Protocol::detect(&[]), None);
+}
+
+#[test]
+fn it_assembles_frame_split_across_notifications() {
+    let frame = hex!("55ab1001009a70888f3a27d8378bb07f7d8ce4cce88ab54a50595ad6c019c7f2");
+    let mut assembler = FrameAssembler::new();
+
+    assert_eq!(assembler.push(&frame[..20]), None);
+    assert_eq!(assembler.expected_len(), Some(32));
+    assert_eq!(assembler.push(&frame[20..]), Some(frame.to_vec()));
+    assert_eq!(assembler.take(), Vec::<u8>::new());
+}
+
+#[test]
+fn it_keeps_bytes_of_next_frame() {
+    let mut assembler = FrameAssembler::new();
+
+    assert_eq!(assembler.push(&hex!("55 aa 04 23 01 32")), None);
+    assert_eq!(assembler.push(&hex!("40 00 65 ff 5a a5")), Some(hex!("55 aa 04 23 01 32 40 00 65 ff").to_vec()));
+    assert_eq!(assembler.push(&hex!("02 23 3e 01 32 40 00 29 ff")), Some(hex!("5a a5 02 23 3e 01 32 40 00 29 ff").to_vec()));
+}
+
+#[test]
+fn it_does_not_know_length_of_unknown_frames() {
+    let mut assembler = FrameAssembler::new();
+
+    assert_eq!(assembler.push(b"AT\r\n"), None);
+    assert_eq!(assembler.expected_len(), None);
+    assert_eq!(assembler.take(), b"AT\r\n".to_vec());
+}
+
 #[test]
 fn it_encrypts_uart() {
     let encryption_key = EncryptionKey {
@@ -47,3 +135,37 @@ fn it_decrypt_uart() {
 
     assert_eq!("26354/00467353", text)
 }
+
+#[test]
+fn it_reuses_buffers_for_uart_frames() {
+    let encryption_key = EncryptionKey {
+        key: hex!("5066d82368375a1f6a0a3eba1317b525"),
+        iv: hex!("28cee53e"),
+    };
+
+    let cmd = ScooterCommand {
+        direction: Direction::MasterToMotor,
+        read_write: ReadWrite::Read,
+        attribute: Attribute::GeneralInfo,
+        payload: vec![0x0e],
+    };
+    let mut command = vec![0xFF; 32];
+    cmd.write_bytes(&mut command);
+    assert_eq!(command, hex!("032001100e"));
+
+    let mut frame = vec![0xFF; 64];
+    encrypt_uart_into(&encryption_key, &command, 0, Some(hex!("897045e7")), &mut frame);
+    assert_eq!(frame, hex!("55ab03000016b2eddb0b680532a988c4f2dbf9"));
+
+    let decryption_key = EncryptionKey {
+        key: hex!("462f3fcc74200ca5f77ee2a581c42af0"),
+        iv: hex!("f8901a05"),
+    };
+    let encrypted = hex!("55ab1001009a70888f3a27d8378bb07f7d8ce4cce88ab54a50595ad6c019c7f2");
+    let mut response = vec![0xFF; 64];
+    decrypt_uart_into(&decryption_key, &encrypted, &mut response).unwrap();
+    assert_eq!(response, decrypt_uart(&decryption_key, &encrypted).unwrap());
+
+    let mut assembler = FrameAssembler::with_buffer(response);
+    assert_eq!(assembler.push(&hex!("55aa0320011a02bfff")), Some(hex!("55aa0320011a02bfff").to_vec()));
+}
@@ -0,0 +1,28 @@
+use ninebot_ble::util::{with_timeout, Timeout};
+use std::time::Duration;
+
+#[tokio::test]
+async fn it_returns_output_before_timeout() {
+    let output = with_timeout(async { 42 }, Duration::from_secs(1)).await;
+
+    assert_eq!(output, Ok(42));
+}
+
+#[tokio::test]
+async fn it_reports_timeout_with_duration() {
+    let duration = Duration::from_millis(10);
+    let output = with_timeout(std::future::pending::<()>(), duration).await;
+
+    assert_eq!(output, Err(Timeout(duration)));
+}
+
+#[tokio::test]
+async fn it_keeps_timeout_type_through_anyhow() {
+    let err: anyhow::Error = with_timeout(std::future::pending::<()>(), Duration::from_millis(10))
+        .await
+        .unwrap_err()
+        .into();
+
+    assert!(err.is::<Timeout>());
+    assert_eq!(err.to_string(), "No response in 10ms");
+}